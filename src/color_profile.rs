@@ -0,0 +1,125 @@
+use std::convert::TryFrom;
+
+use crate::{Chunk, Fixed};
+
+const FIXED_GAMMA_FLAG: u16 = 1;
+
+/// The color space a sprite's pixels were authored in.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum ColorProfile {
+    /// No color profile was given. Aseprite treats these sprites as sRGB.
+    #[default]
+    None,
+    Srgb{
+        /// A fixed gamma (1.0 is linear) to use instead of sRGB's own curve.
+        gamma: Option<f32>,
+    },
+    /// An embedded ICC profile.
+    Icc{
+        data: Vec<u8>,
+        /// A fixed gamma (1.0 is linear) to use instead of the profile's own curves.
+        gamma: Option<f32>,
+    },
+}
+
+impl ColorProfile {
+    pub(crate) fn from_chunk(chunk: &Chunk) -> Option<ColorProfile> {
+        if let Chunk::ColorProfile{profile_type, flags, gamma, icc_data, ..} = chunk {
+            let gamma = if flags & FIXED_GAMMA_FLAG != 0 {
+                Some(fixed_to_f32(*gamma))
+            } else {
+                None
+            };
+
+            match ColorProfileType::try_from(*profile_type).ok()? {
+                ColorProfileType::None => Some(ColorProfile::None),
+                ColorProfileType::Srgb => Some(ColorProfile::Srgb{gamma}),
+                ColorProfileType::Icc => Some(ColorProfile::Icc{data: icc_data.clone(), gamma}),
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Converts straight (non-premultiplied) RGBA pixel data from this profile into sRGB in
+    /// place. Returns false if the profile couldn't be understood, in which case the pixels are
+    /// left untouched.
+    pub fn to_srgb(&self, rgba: &mut [u8]) -> bool {
+        match self {
+            ColorProfile::None | ColorProfile::Srgb{gamma: None} => true,
+            ColorProfile::Srgb{gamma: Some(gamma)} | ColorProfile::Icc{gamma: Some(gamma), ..} => {
+                // a fixed gamma overrides the transfer function, which is the only part of the
+                // conversion that isn't a no-op for sRGB primaries.
+                let table = gamma_table(*gamma);
+                for pixel in rgba.chunks_mut(4) {
+                    for channel in &mut pixel[..3] {
+                        *channel = table[*channel as usize];
+                    }
+                }
+
+                true
+            },
+            ColorProfile::Icc{data, gamma: None} => {
+                let transform = qcms::Profile::new_from_slice(data, false).and_then(|input| {
+                    let mut output = qcms::Profile::new_sRGB();
+                    output.precache_output_transform();
+                    qcms::Transform::new(&input, &output, qcms::DataType::RGBA8, qcms::Intent::Perceptual)
+                });
+
+                match transform {
+                    Some(transform) => {
+                        let len = rgba.len() - rgba.len() % 4;
+                        transform.apply(&mut rgba[..len]);
+                        true
+                    },
+                    None => false,
+                }
+            },
+        }
+    }
+}
+
+// mirrors the profile type values stored in the color profile chunk.
+enum ColorProfileType {
+    None,
+    Srgb,
+    Icc,
+}
+
+impl TryFrom<u16> for ColorProfileType {
+    type Error = u16;
+
+    fn try_from(raw: u16) -> std::result::Result<ColorProfileType, u16> {
+        match raw {
+            0 => Ok(ColorProfileType::None),
+            1 => Ok(ColorProfileType::Srgb),
+            2 => Ok(ColorProfileType::Icc),
+            _ => Err(raw),
+        }
+    }
+}
+
+pub(crate) fn validate_profile_type(raw: u16) -> std::result::Result<(), u16> {
+    ColorProfileType::try_from(raw).map(|_| ())
+}
+
+fn fixed_to_f32(value: Fixed) -> f32 {
+    value.to_bits() as f32 / 65536.0
+}
+
+// maps gamma encoded channel values to sRGB encoded ones.
+fn gamma_table(gamma: f32) -> [u8; 256] {
+    let mut table = [0; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let linear = (i as f32 / 255.0).powf(gamma);
+        let srgb = if linear <= 0.003_130_8 {
+            linear * 12.92
+        } else {
+            1.055 * linear.powf(1.0 / 2.4) - 0.055
+        };
+
+        *entry = (srgb * 255.0).round().clamp(0.0, 255.0) as u8;
+    }
+
+    table
+}
@@ -0,0 +1,87 @@
+use std::fmt;
+use std::slice;
+
+/// Something odd about a file that didn't stop it from being read or rendered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// A chunk of a type this crate doesn't know about was skipped.
+    UnknownChunk{
+        offset: usize,
+        chunk_type: u16,
+    },
+    /// A chunk Aseprite no longer writes, like the mask chunk, was read.
+    DeprecatedChunk{
+        offset: usize,
+        chunk_type: u16,
+    },
+    /// The palette was built from the old palette chunks, since the file has no new one.
+    OldPalette,
+    /// A cel reached past the edge of the canvas, so only part of it was drawn.
+    ClippedCel{
+        frame: usize,
+        layer: usize,
+    },
+    /// A tilemap cel wasn't drawn because its layer's tileset has no tiles to draw it with, like
+    /// an external tileset that hasn't been resolved.
+    MissingTiles{
+        frame: usize,
+        layer: usize,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::UnknownChunk{offset, chunk_type} => write!(
+                f, "skipped unknown chunk type {:#06x} at offset {}", chunk_type, offset
+            ),
+            Warning::DeprecatedChunk{offset, chunk_type} => write!(
+                f, "read deprecated chunk type {:#06x} at offset {}", chunk_type, offset
+            ),
+            Warning::OldPalette => write!(f, "palette built from deprecated old palette chunks"),
+            Warning::ClippedCel{frame, layer} => write!(
+                f, "cel on layer {} of frame {} was clipped to the canvas", layer, frame
+            ),
+            Warning::MissingTiles{frame, layer} => write!(
+                f, "tilemap on layer {} of frame {} has no tiles to draw with", layer, frame
+            ),
+        }
+    }
+}
+
+/// The warnings collected while parsing or rendering. With the `log` feature enabled every
+/// warning is also logged as it happens.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    warnings: Vec<Warning>,
+}
+
+impl Diagnostics {
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Warning> {
+        self.warnings.iter()
+    }
+
+    pub(crate) fn warn(&mut self, warning: Warning) {
+        #[cfg(feature = "log")]
+        log::warn!("{}", warning);
+
+        self.warnings.push(warning);
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Warning;
+    type IntoIter = slice::Iter<'a, Warning>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.warnings.iter()
+    }
+}
@@ -0,0 +1,117 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str::Utf8Error;
+
+pub type Result<T> = std::result::Result<T, AseError>;
+
+/// Everything that can go wrong while parsing an aseprite file. Every variant carries the byte
+/// offset into the file where the problem was found.
+#[derive(Debug)]
+pub enum AseError {
+    /// A header or frame didn't start with the expected magic number.
+    BadMagic{
+        offset: usize,
+        expected: u16,
+        found: u16,
+    },
+    /// The data ended before a complete value could be read.
+    Truncated{
+        offset: usize,
+        needed: usize,
+        available: usize,
+    },
+    /// A field held a value that doesn't map to any known variant (chunk type, cel type, etc.).
+    UnknownValue{
+        offset: usize,
+        kind: &'static str,
+        value: u32,
+    },
+    /// Values that can each be read fine don't make sense together, like a palette range that
+    /// ends past the palette.
+    Invalid{
+        offset: usize,
+        reason: &'static str,
+    },
+    /// ZLIB compressed data couldn't be inflated.
+    Decompression{
+        offset: usize,
+        source: io::Error,
+    },
+    /// A string wasn't valid UTF-8.
+    InvalidUtf8{
+        offset: usize,
+        source: Utf8Error,
+    },
+    /// A file referenced from the External Files chunk couldn't be loaded. The offset is where
+    /// the reference was found.
+    ExternalFile{
+        offset: usize,
+        name: String,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    /// Reading from the underlying reader failed. The offset is how far into the file it got.
+    Io{
+        offset: usize,
+        source: io::Error,
+    },
+}
+
+impl AseError {
+    /// The byte offset into the file where the error occurred.
+    pub fn offset(&self) -> usize {
+        match self {
+            AseError::BadMagic{offset, ..} => *offset,
+            AseError::Truncated{offset, ..} => *offset,
+            AseError::UnknownValue{offset, ..} => *offset,
+            AseError::Invalid{offset, ..} => *offset,
+            AseError::Decompression{offset, ..} => *offset,
+            AseError::InvalidUtf8{offset, ..} => *offset,
+            AseError::ExternalFile{offset, ..} => *offset,
+            AseError::Io{offset, ..} => *offset,
+        }
+    }
+}
+
+impl fmt::Display for AseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AseError::BadMagic{offset, expected, found} => write!(
+                f, "bad magic number at offset {}: expected {:#06x}, found {:#06x}", offset, expected, found
+            ),
+            AseError::Truncated{offset, needed, available} => write!(
+                f, "truncated data at offset {}: needed {} bytes, {} available", offset, needed, available
+            ),
+            AseError::UnknownValue{offset, kind, value} => write!(
+                f, "unknown {} {:#x} at offset {}", kind, value, offset
+            ),
+            AseError::Invalid{offset, reason} => write!(
+                f, "invalid data at offset {}: {}", offset, reason
+            ),
+            AseError::Decompression{offset, source} => write!(
+                f, "failed to decompress data at offset {}: {}", offset, source
+            ),
+            AseError::InvalidUtf8{offset, source} => write!(
+                f, "invalid UTF-8 string at offset {}: {}", offset, source
+            ),
+            AseError::ExternalFile{offset, name, source} => write!(
+                f, "failed to load external file {:?} referenced at offset {}: {}", name, offset, source
+            ),
+            AseError::Io{offset, source} => write!(
+                f, "failed to read at offset {}: {}", offset, source
+            ),
+        }
+    }
+}
+
+impl Error for AseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AseError::Decompression{source, ..} => Some(source),
+            AseError::InvalidUtf8{source, ..} => Some(source),
+            AseError::ExternalFile{source, ..} => Some(source.as_ref()),
+            AseError::Io{source, ..} => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! PNG export for rendered frames.
+
+use std::io::Write;
+
+use png::{BitDepth, ColorType, Encoder, EncodingError};
+
+use crate::{GrayImage, Image, IndexedImage};
+
+impl Image {
+    /// Writes the image as an 8-bit RGBA PNG.
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<(), EncodingError> {
+        write_png(writer, self.width(), self.height(), ColorType::Rgba, self.data(), |_| ())
+    }
+}
+
+impl GrayImage {
+    /// Writes the image as an 8-bit grayscale PNG with alpha.
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<(), EncodingError> {
+        write_png(writer, self.width(), self.height(), ColorType::GrayscaleAlpha, self.data(), |_| ())
+    }
+}
+
+impl IndexedImage {
+    /// Writes the image as an 8-bit paletted PNG. The transparent index is written as fully
+    /// transparent, and the palette is padded when pixels point past its end.
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<(), EncodingError> {
+        let used = self.indices().iter().max().map_or(1, |&index| index as usize + 1);
+        let size = self.palette().len().clamp(used, 256);
+        let mut rgb = Vec::with_capacity(size * 3);
+        let mut alpha = Vec::with_capacity(size);
+        for index in 0..size {
+            let color = self.palette().get(index).copied().unwrap_or_default();
+            rgb.extend_from_slice(&color[..3]);
+            alpha.push(if index == self.transparent_index() as usize { 0 } else { color[3] });
+        }
+
+        write_png(writer, self.width(), self.height(), ColorType::Indexed, self.indices(), |encoder| {
+            encoder.set_palette(rgb);
+            encoder.set_trns(alpha);
+        })
+    }
+}
+
+fn write_png<W, F>(writer: W, width: usize, height: usize, color_type: ColorType, data: &[u8], setup: F) -> Result<(), EncodingError>
+where
+    W: Write,
+    F: FnOnce(&mut Encoder<'_, W>),
+{
+    let mut encoder = Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(color_type);
+    encoder.set_depth(BitDepth::Eight);
+    setup(&mut encoder);
+    encoder.write_header()?.write_image_data(data)
+}
@@ -0,0 +1,192 @@
+use std::collections::hash_map::{Entry, HashMap};
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{Ase, AseError, Raw, Result};
+
+/// A file or extension referenced from the External Files chunk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalFile {
+    /// The ID other chunks use to refer to this entry.
+    pub id: u32,
+    pub file_type: ExternalFileType,
+    /// A file name relative to the sprite for palettes and tilesets, or an extension ID.
+    pub name: String,
+    // where the entry starts in the file, for reporting errors while resolving it.
+    pub(crate) offset: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExternalFileType {
+    Palette,
+    Tileset,
+    /// The extension owning a properties map in user data.
+    PropertiesExtension,
+    /// The extension managing a tileset's tiles.
+    TileManagementExtension,
+}
+
+impl TryFrom<u8> for ExternalFileType {
+    type Error = u8;
+
+    fn try_from(raw: u8) -> std::result::Result<ExternalFileType, u8> {
+        match raw {
+            0 => Ok(ExternalFileType::Palette),
+            1 => Ok(ExternalFileType::Tileset),
+            2 => Ok(ExternalFileType::PropertiesExtension),
+            3 => Ok(ExternalFileType::TileManagementExtension),
+            _ => Err(raw),
+        }
+    }
+}
+
+impl ExternalFile {
+    // returns the entry along with the number of bytes it occupied.
+    pub(crate) fn new(raw: Raw) -> Result<(ExternalFile, usize)> {
+        let file_type = ExternalFileType::try_from(raw.byte(4)?)
+            .map_err(|value| raw.unknown(4, "external file type", value))?;
+        // 7 reserved bytes
+        let (name, name_size) = raw.string(12)?;
+        let file = ExternalFile{
+            id: raw.dword(0)?,
+            file_type,
+            name,
+            offset: raw.offset,
+        };
+
+        Ok((file, 12 + name_size))
+    }
+
+    fn error<E>(&self, source: E) -> AseError
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        AseError::ExternalFile{
+            offset: self.offset,
+            name: self.name.clone(),
+            source: source.into(),
+        }
+    }
+}
+
+/// Loads the files a sprite references through its External Files chunk. Implement this to pull
+/// them out of an asset archive or anywhere else; closures taking an ExternalFile work too.
+pub trait ExternalFileResolver {
+    /// Returns the contents of the referenced file.
+    fn resolve(&mut self, file: &ExternalFile) -> io::Result<Vec<u8>>;
+}
+
+impl<F> ExternalFileResolver for F
+where
+    F: FnMut(&ExternalFile) -> io::Result<Vec<u8>>,
+{
+    fn resolve(&mut self, file: &ExternalFile) -> io::Result<Vec<u8>> {
+        self(file)
+    }
+}
+
+/// Reads external files from disk, relative to the directory the sprite lives in.
+///
+/// Names come from the sprite, so only files inside that directory can be read: absolute paths
+/// and names climbing out through `..` are refused.
+#[derive(Debug, Clone)]
+pub struct FileSystemResolver {
+    dir: PathBuf,
+}
+
+impl FileSystemResolver {
+    /// Resolves files relative to the directory containing the sprite at the given path.
+    pub fn new<P: AsRef<Path>>(sprite_path: P) -> FileSystemResolver {
+        let dir = sprite_path.as_ref()
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        FileSystemResolver::from_dir(dir)
+    }
+
+    /// Resolves files relative to the given directory.
+    pub fn from_dir<P: Into<PathBuf>>(dir: P) -> FileSystemResolver {
+        FileSystemResolver{
+            dir: dir.into(),
+        }
+    }
+}
+
+impl ExternalFileResolver for FileSystemResolver {
+    fn resolve(&mut self, file: &ExternalFile) -> io::Result<Vec<u8>> {
+        let name = Path::new(&file.name);
+        let confined = name.components().all(|component| match component {
+            Component::Normal(_) | Component::CurDir => true,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => false,
+        });
+        if !confined {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} is outside the sprite's directory", file.name),
+            ));
+        }
+
+        fs::read(self.dir.join(name))
+    }
+}
+
+// loads every external palette and tileset the sprite references and copies what it needs into
+// the sprite. Each file is only loaded once, no matter how many things point at it.
+pub(crate) fn resolve<R: ExternalFileResolver>(ase: &mut Ase, resolver: &mut R) -> Result<()> {
+    let mut loaded = HashMap::new();
+    for tileset in &mut ase.tilesets {
+        let reference = match tileset.external {
+            Some(reference) if !tileset.has_embedded_tiles() => reference,
+            _ => continue,
+        };
+
+        let file = ase.external_files.iter()
+            .find(|file| file.id == reference.file_id)
+            .ok_or(AseError::UnknownValue{
+                offset: reference.offset,
+                kind: "external file id",
+                value: reference.file_id,
+            })?;
+        let source = load(&mut loaded, resolver, file)?.tilesets.iter()
+            .find(|source| source.id == reference.tileset_id)
+            .ok_or_else(|| file.error(format!("no tileset with id {}", reference.tileset_id)))?;
+        if (source.tile_width, source.tile_height) != (tileset.tile_width, tileset.tile_height) {
+            return Err(file.error(format!(
+                "tileset {} has {}x{} tiles, expected {}x{}", source.id, source.tile_width,
+                source.tile_height, tileset.tile_width, tileset.tile_height
+            )));
+        }
+
+        tileset.tile_count = source.tile_count;
+        tileset.pixels = source.pixels.clone();
+    }
+
+    // a sprite with its own palette never needs an external one
+    if ase.palette.is_empty() {
+        let file = ase.external_files.iter().find(|file| file.file_type == ExternalFileType::Palette);
+        if let Some(file) = file {
+            ase.palette = mem::take(&mut load(&mut loaded, resolver, file)?.palette);
+        }
+    }
+
+    Ok(())
+}
+
+fn load<'a, R: ExternalFileResolver>(
+    loaded: &'a mut HashMap<u32, Ase>,
+    resolver: &mut R,
+    file: &ExternalFile,
+) -> Result<&'a mut Ase> {
+    match loaded.entry(file.id) {
+        Entry::Occupied(entry) => Ok(entry.into_mut()),
+        Entry::Vacant(entry) => {
+            let bytes = resolver.resolve(file).map_err(|err| file.error(err))?;
+            let external = Ase::new(&bytes).map_err(|err| file.error(err))?;
+            Ok(entry.insert(external))
+        },
+    }
+}
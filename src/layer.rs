@@ -0,0 +1,181 @@
+use std::convert::TryFrom;
+
+use bitflags::bitflags;
+
+use crate::{Raw, Result, UserData};
+
+bitflags! {
+    pub struct LayerFlags: u16 {
+        const VISIBLE = 1;
+        const EDITABLE = 2;
+        const LOCK_MOVEMENT = 4;
+        const BACKGROUND = 8;
+        const PREFER_LINKED_CELS = 16;
+        /// The group is shown collapsed in the layer panel.
+        const COLLAPSED = 32;
+        /// A reference layer, which is only there to guide the artist and never exported.
+        const REFERENCE = 64;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    flags: LayerFlags,
+    layer_type: LayerType,
+    child_level: u16,
+    default_width: u16,
+    default_height: u16,
+    blend_mode: BlendMode,
+    opacity: u8,
+    name: String,
+    tileset_index: Option<u32>,
+    pub(crate) user_data: Option<UserData>,
+}
+
+impl Layer {
+    pub(crate) fn new(raw: Raw) -> Result<Layer> {
+        let layer_type = LayerType::try_from(raw.word(2)?)
+            .map_err(|value| raw.unknown(2, "layer type", value))?;
+        let blend_mode = BlendMode::try_from(raw.word(10)?)
+            .map_err(|value| raw.unknown(10, "blend mode", value))?;
+        let (name, name_size) = raw.string(16)?;
+        let tileset_index = match layer_type {
+            LayerType::Tilemap => Some(raw.dword(16 + name_size)?),
+            _ => None,
+        };
+
+        Ok(Layer{
+            // unknown bits are dropped rather than rejected, newer versions keep adding flags
+            flags: LayerFlags::from_bits_truncate(raw.word(0)?),
+            layer_type,
+            child_level: raw.word(4)?,
+            default_width: raw.word(6)?,
+            default_height: raw.word(8)?,
+            blend_mode,
+            opacity: raw.byte(12)?,
+            // 3 unused bytes
+            name,
+            tileset_index,
+            user_data: None,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn flags(&self) -> LayerFlags {
+        self.flags
+    }
+
+    /// Whether the layer itself is visible. A layer inside a hidden group isn't drawn either way.
+    pub fn is_visible(&self) -> bool {
+        self.flags.contains(LayerFlags::VISIBLE)
+    }
+
+    pub fn layer_type(&self) -> LayerType {
+        self.layer_type
+    }
+
+    /// How deeply the layer is nested in groups. Top level layers are at 0, and a layer belongs
+    /// to the closest group before it with a lower child level.
+    pub fn child_level(&self) -> u16 {
+        self.child_level
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    /// The layer's opacity, from 0 (transparent) to 255 (opaque).
+    pub fn opacity(&self) -> u8 {
+        self.opacity
+    }
+
+    pub fn user_data(&self) -> Option<&UserData> {
+        self.user_data.as_ref()
+    }
+
+    // the size Aseprite gives new cels on the layer, which nothing uses anymore.
+    pub(crate) fn default_size(&self) -> (u16, u16) {
+        (self.default_width, self.default_height)
+    }
+
+    /// The index of the tileset used by a tilemap layer.
+    pub fn tileset_index(&self) -> Option<u32> {
+        self.tileset_index
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerType {
+    Normal,
+    Group,
+    Tilemap,
+}
+
+impl TryFrom<u16> for LayerType {
+    type Error = u16;
+
+    fn try_from(raw: u16) -> std::result::Result<LayerType, u16> {
+        match raw {
+            0 => Ok(LayerType::Normal),
+            1 => Ok(LayerType::Group),
+            2 => Ok(LayerType::Tilemap),
+            _ => Err(raw),
+        }
+    }
+}
+
+/// How a layer's pixels are combined with the layers below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+    Addition,
+    Subtract,
+    Divide,
+}
+
+impl TryFrom<u16> for BlendMode {
+    type Error = u16;
+
+    fn try_from(raw: u16) -> std::result::Result<BlendMode, u16> {
+        match raw {
+            0 => Ok(BlendMode::Normal),
+            1 => Ok(BlendMode::Multiply),
+            2 => Ok(BlendMode::Screen),
+            3 => Ok(BlendMode::Overlay),
+            4 => Ok(BlendMode::Darken),
+            5 => Ok(BlendMode::Lighten),
+            6 => Ok(BlendMode::ColorDodge),
+            7 => Ok(BlendMode::ColorBurn),
+            8 => Ok(BlendMode::HardLight),
+            9 => Ok(BlendMode::SoftLight),
+            10 => Ok(BlendMode::Difference),
+            11 => Ok(BlendMode::Exclusion),
+            12 => Ok(BlendMode::Hue),
+            13 => Ok(BlendMode::Saturation),
+            14 => Ok(BlendMode::Color),
+            15 => Ok(BlendMode::Luminosity),
+            16 => Ok(BlendMode::Addition),
+            17 => Ok(BlendMode::Subtract),
+            18 => Ok(BlendMode::Divide),
+            _ => Err(raw),
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{Layer, LayerType};
+
+/// The sprite's layers arranged into their group hierarchy, rebuilt from each layer's child
+/// level.
+#[derive(Debug)]
+pub struct LayerTree<'a> {
+    layers: &'a [Layer],
+    parents: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+    roots: Vec<usize>,
+}
+
+impl<'a> LayerTree<'a> {
+    pub(crate) fn new(layers: &'a [Layer]) -> LayerTree<'a> {
+        let mut parents = Vec::with_capacity(layers.len());
+        let mut children = vec![Vec::new(); layers.len()];
+        let mut roots = Vec::new();
+        // the chain of layers leading up to the current one, innermost last
+        let mut stack: Vec<usize> = Vec::new();
+        for (index, layer) in layers.iter().enumerate() {
+            while let Some(&last) = stack.last() {
+                if layers[last].child_level() < layer.child_level() {
+                    break;
+                }
+
+                stack.pop();
+            }
+
+            let parent = stack.last().copied();
+            match parent {
+                Some(parent) => children[parent].push(index),
+                None => roots.push(index),
+            }
+
+            parents.push(parent);
+            stack.push(index);
+        }
+
+        LayerTree{
+            layers,
+            parents,
+            children,
+            roots,
+        }
+    }
+
+    /// The layers that aren't inside any group, from bottom to top.
+    pub fn roots(&self) -> impl Iterator<Item = LayerNode<'_>> {
+        self.roots.iter().map(move |&index| self.node(index))
+    }
+
+    /// Every layer in z-order from bottom to top. A group comes right before its children, which
+    /// is the order they appear in the file.
+    pub fn iter(&self) -> impl Iterator<Item = LayerNode<'_>> {
+        (0..self.layers.len()).map(move |index| self.node(index))
+    }
+
+    /// Returns the layer with the given index into `Ase::layers`.
+    pub fn get(&self, index: usize) -> Option<LayerNode<'_>> {
+        if index < self.layers.len() {
+            Some(self.node(index))
+        } else {
+            None
+        }
+    }
+
+    /// Looks up a layer by the names of the groups leading to it, separated by slashes, e.g.
+    /// "body/arm/left". When siblings share a name the bottom-most one wins.
+    pub fn find(&self, path: &str) -> Option<LayerNode<'_>> {
+        let mut candidates = &self.roots;
+        let mut found = None;
+        for name in path.split('/') {
+            let index = *candidates.iter().find(|&&index| self.layers[index].name() == name)?;
+            candidates = &self.children[index];
+            found = Some(index);
+        }
+
+        found.map(|index| self.node(index))
+    }
+
+    fn node(&self, index: usize) -> LayerNode<'_> {
+        LayerNode{
+            tree: self,
+            index,
+        }
+    }
+}
+
+/// A layer's place in the LayerTree.
+#[derive(Debug, Clone, Copy)]
+pub struct LayerNode<'a> {
+    tree: &'a LayerTree<'a>,
+    index: usize,
+}
+
+impl<'a> LayerNode<'a> {
+    /// The layer's index into `Ase::layers`, which is what cels refer to.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn layer(&self) -> &'a Layer {
+        &self.tree.layers[self.index]
+    }
+
+    pub fn is_group(&self) -> bool {
+        self.layer().layer_type() == LayerType::Group
+    }
+
+    /// The group containing this layer, if it isn't at the top level.
+    pub fn parent(&self) -> Option<LayerNode<'a>> {
+        self.tree.parents[self.index].map(|index| self.tree.node(index))
+    }
+
+    /// The layers directly inside this group, from bottom to top.
+    pub fn children(&self) -> impl Iterator<Item = LayerNode<'a>> {
+        let tree = self.tree;
+        tree.children[self.index].iter().map(move |&index| tree.node(index))
+    }
+
+    /// Every group containing this layer, starting with its parent.
+    pub fn ancestors(&self) -> impl Iterator<Item = LayerNode<'a>> {
+        std::iter::successors(self.parent(), LayerNode::parent)
+    }
+
+    /// The slash separated path that finds this layer with `LayerTree::find`.
+    pub fn path(&self) -> String {
+        let mut names: Vec<&str> = self.ancestors().map(|node| node.layer().name()).collect();
+        names.reverse();
+        names.push(self.layer().name());
+        names.join("/")
+    }
+
+    /// Whether the layer ends up drawn: it and every group containing it have to be visible.
+    pub fn is_visible(&self) -> bool {
+        self.layer().is_visible() && self.ancestors().all(|node| node.layer().is_visible())
+    }
+}
@@ -1,11 +1,61 @@
 use flate2::read::ZlibDecoder;
-use std::io::Read;
-use std::fmt;
-
-type Fixed = fixed::FixedI32<fixed::frac::U2>;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::sync::OnceLock;
+
+mod blend;
+mod color_profile;
+mod diagnostics;
+mod error;
+#[cfg(feature = "png")]
+mod export;
+mod external;
+mod layer;
+mod layer_tree;
+mod palette;
+mod pixels;
+mod reader;
+mod render;
+mod slice;
+mod tag;
+mod tileset;
+mod user_data;
+mod writer;
+#[cfg(test)]
+mod test_util;
+
+pub use color_profile::ColorProfile;
+pub use diagnostics::{Diagnostics, Warning};
+pub use error::{AseError, Result};
+pub use external::{ExternalFile, ExternalFileResolver, ExternalFileType, FileSystemResolver};
+pub use layer::{BlendMode, Layer, LayerFlags, LayerType};
+pub use layer_tree::{LayerNode, LayerTree};
+pub use palette::Palette;
+pub use pixels::{GrayA8, Pixels, Rgba8};
+pub use reader::FrameReader;
+pub use render::{GrayImage, Image, IndexedImage, RenderOptions, RenderedFrame};
+pub use slice::{Slice, SliceKey};
+pub use tag::{LoopDirection, Tag};
+pub use tileset::{ExternalTileset, Tile, TilemapCel, Tileset};
+pub use user_data::{Properties, UserData, Value};
+
+type Fixed = fixed::FixedI32<fixed::frac::U16>;
 
 const HEADER_SIZE: usize = 128;
 const FRAME_HEADER_SIZE: usize = 16;
+const CHUNK_HEADER_SIZE: usize = 6;
+
+// palettes bigger than this are treated as corrupt rather than allocated.
+const MAX_PALETTE_SIZE: u32 = 0x10000;
+
+// header flags
+const LAYER_OPACITY_FLAG: u32 = 1;
+const COMPOSITE_GROUPS_FLAG: u32 = 2;
+
+const HEADER_MAGIC: u16 = 0xA5E0;
+const FRAME_MAGIC: u16 = 0xF1FA;
 
 #[derive(Default, Debug)]
 pub struct Header {
@@ -17,6 +67,7 @@ pub struct Header {
     color_depth: ColorDepth,
     flags: u32,
     speed: u16,
+    // the palette index that's see-through in indexed sprites.
     pallette_entry: u8,
     number_of_colors: u16,
     pixel_width: u8,
@@ -31,75 +82,222 @@ pub struct Frame {
     pub frame_duration: u16,
     pub new_chunks: u32,
     pub chunks: Vec<Chunk>,
-    pub layers: Vec<Layer>,
+    // cels indexed by the layer they belong to.
+    cels: Vec<Option<Cel>>,
 }
 
 impl Frame {
-    pub fn new(header: &Header, raw: &[u8]) -> Frame {
-        let mut frame = Frame{
-            size: read_dword(&raw[0..]),
-            magic_number: read_word(&raw[4..]),
-            old_chunks: read_word(&raw[6..]),
-            frame_duration: read_word(&raw[8..]),
-            new_chunks: read_dword(&raw[12..]),
-            chunks: Vec::new(),
-            layers: Vec::new(),
-        };
+    pub fn new(header: &Header, raw: &[u8]) -> Result<Frame> {
+        Frame::parse(header, Raw::new(raw), &mut Diagnostics::default())
+    }
 
+    fn parse(header: &Header, raw: Raw, diagnostics: &mut Diagnostics) -> Result<Frame> {
+        let mut frame = Frame::parse_header(raw)?;
+        let raw = raw.slice(0, frame.size as usize)?;
         let mut offset = FRAME_HEADER_SIZE;
-        let chunk_count = if frame.new_chunks == 0 {
-            frame.old_chunks as u32
+        let mut user_data_target = None;
+        for _ in 0..frame.chunk_count() {
+            let (chunk, size) = Chunk::parse(header, raw.rest(offset)?)?;
+            frame.add_chunk(chunk, raw.offset + offset, &mut user_data_target, diagnostics);
+            offset += size as usize;
+        }
+
+        Ok(frame)
+    }
+
+    // reads the 16 byte frame header, leaving the frame without any chunks.
+    fn parse_header(raw: Raw) -> Result<Frame> {
+        let size = raw.dword(0)?;
+        if (size as usize) < FRAME_HEADER_SIZE {
+            return Err(raw.truncated(0, FRAME_HEADER_SIZE));
+        }
+
+        let magic_number = raw.word(4)?;
+        if magic_number != FRAME_MAGIC {
+            return Err(AseError::BadMagic{
+                offset: raw.offset + 4,
+                expected: FRAME_MAGIC,
+                found: magic_number,
+            });
+        }
+
+        Ok(Frame{
+            size,
+            magic_number,
+            old_chunks: raw.word(6)?,
+            frame_duration: raw.word(8)?,
+            new_chunks: raw.dword(12)?,
+            chunks: Vec::new(),
+            cels: Vec::new(),
+        })
+    }
+
+    fn chunk_count(&self) -> u32 {
+        if self.new_chunks == 0 {
+            self.old_chunks as u32
         } else {
-            frame.new_chunks
-        };
+            self.new_chunks
+        }
+    }
 
-        for _ in 0..chunk_count {
-            let (chunk, size) = Chunk::new(header, &raw[offset..]);
-            offset += size as usize;
-            match chunk {
-                Chunk::Layer(layer) => frame.layers.push(layer),
-                Chunk::Cel(cel) => frame.layers[cel.layer_index() as usize].cels.push(cel),
-                _ => frame.chunks.push(chunk),
-            }
+    // files the chunk found at the given offset under the frame. User data chunks belong to
+    // whichever object came before them, which the target keeps track of from one chunk to the
+    // next.
+    fn add_chunk(
+        &mut self,
+        chunk: Chunk,
+        offset: usize,
+        user_data_target: &mut Option<UserDataTarget>,
+        diagnostics: &mut Diagnostics,
+    ) {
+        match chunk {
+            Chunk::Unknown{chunk_type, ..} => diagnostics.warn(Warning::UnknownChunk{offset, chunk_type}),
+            Chunk::Mask{..} => diagnostics.warn(Warning::DeprecatedChunk{offset, chunk_type: 0x2016}),
+            Chunk::Path => diagnostics.warn(Warning::DeprecatedChunk{offset, chunk_type: 0x2017}),
+            _ => (),
         }
 
-        frame
+        match chunk {
+            Chunk::UserData(user_data) => self.attach_user_data(user_data_target, user_data),
+            Chunk::Cel(cel) => {
+                *user_data_target = Some(UserDataTarget::Cel(cel.layer_index() as usize));
+                self.insert_cel(cel);
+            },
+            _ => {
+                if let Some(target) = UserDataTarget::for_chunk(&chunk, self.chunks.len()) {
+                    *user_data_target = Some(target);
+                }
+
+                self.chunks.push(chunk);
+            },
+        }
     }
-}
 
-#[derive(Debug)]
-pub struct Layer {
-    flags: u16,
-    layer_type: LayerType,
-    child_level: u16,
-    default_width: u16,
-    default_height: u16,
-    blend_mode: u16,
-    opacity: u8,
-    name: String,
-    cels: Vec<Cel>,
+    fn attach_user_data(&mut self, target: &mut Option<UserDataTarget>, user_data: UserData) {
+        match target {
+            Some(UserDataTarget::Cel(layer_index)) => {
+                if let Some(Some(cel)) = self.cels.get_mut(*layer_index) {
+                    cel.base_mut().user_data = Some(user_data);
+                }
+            },
+            // every tag gets its own user data chunk, one after the other in the same order as
+            // the tags chunk.
+            Some(UserDataTarget::Tag{chunk, tag}) => {
+                if let Chunk::FrameTags{tags} = &mut self.chunks[*chunk] {
+                    if let Some(tag) = tags.get_mut(*tag) {
+                        tag.user_data = Some(user_data);
+                    }
+                }
+
+                *tag += 1;
+            },
+            // the first user data chunk after a tileset belongs to the tileset, and every one after
+            // that belongs to the next tile.
+            Some(UserDataTarget::Tileset{chunk, next}) => {
+                if let Chunk::Tileset(tileset) = &mut self.chunks[*chunk] {
+                    if *next == 0 {
+                        tileset.user_data = Some(user_data);
+                    } else {
+                        tileset.tile_user_data.resize_with(*next, || None);
+                        tileset.tile_user_data[*next - 1] = Some(user_data);
+                    }
+                }
+
+                *next += 1;
+            },
+            Some(UserDataTarget::Chunk(index)) => match &mut self.chunks[*index] {
+                Chunk::Layer(layer) => layer.user_data = Some(user_data),
+                Chunk::Slice(slice) => slice.user_data = Some(user_data),
+                Chunk::Pallette{user_data: palette_user_data, ..} => *palette_user_data = Some(user_data),
+                _ => (),
+            },
+            None => self.chunks.push(Chunk::UserData(user_data)),
+        }
+    }
+
+    fn insert_cel(&mut self, cel: Cel) {
+        let index = cel.layer_index() as usize;
+        if index >= self.cels.len() {
+            self.cels.resize_with(index + 1, || None);
+        }
+
+        self.cels[index] = Some(cel);
+    }
+
+    // Layer and tileset chunks only show up in the first frame but describe the whole sprite, so
+    // they get pulled out of the frame and into the Ase.
+    fn take_layers(&mut self) -> Vec<Layer> {
+        self.take_chunks(|chunk| matches!(chunk, Chunk::Layer(_)))
+            .filter_map(|chunk| match chunk {
+                Chunk::Layer(layer) => Some(layer),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn take_tilesets(&mut self) -> Vec<Tileset> {
+        self.take_chunks(|chunk| matches!(chunk, Chunk::Tileset(_)))
+            .filter_map(|chunk| match chunk {
+                Chunk::Tileset(tileset) => Some(tileset),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // removes every chunk matching the predicate, keeping the rest in order.
+    fn take_chunks<F: Fn(&Chunk) -> bool>(&mut self, predicate: F) -> std::vec::IntoIter<Chunk> {
+        let (taken, kept): (Vec<Chunk>, Vec<Chunk>) = self.chunks.drain(..).partition(predicate);
+        self.chunks = kept;
+        taken.into_iter()
+    }
+
+    /// Returns the cel belonging to the layer at the given index, if this frame has one.
+    pub fn cel(&self, layer_index: usize) -> Option<&Cel> {
+        self.cels.get(layer_index).and_then(Option::as_ref)
+    }
+
+    /// Iterates over every cel in this frame in layer order.
+    pub fn cels(&self) -> impl Iterator<Item = &Cel> {
+        self.cels.iter().filter_map(Option::as_ref)
+    }
 }
 
-#[derive(Debug)]
-enum LayerType {
-    Normal,
-    Group,
+// the object a user data chunk gets attached to.
+enum UserDataTarget {
+    Chunk(usize),
+    Cel(usize),
+    Tag{
+        chunk: usize,
+        tag: usize,
+    },
+    Tileset{
+        chunk: usize,
+        next: usize,
+    },
 }
 
-impl From<u16> for LayerType {
-    fn from(raw: u16) -> LayerType {
-        match raw {
-            0 => LayerType::Normal,
-            1 => LayerType::Group,
-            _ => panic!("Invalid layer type!"),
+impl UserDataTarget {
+    // chunks that can't own user data (cel extras, color profiles, etc.) don't change where the
+    // next user data chunk goes.
+    fn for_chunk(chunk: &Chunk, index: usize) -> Option<UserDataTarget> {
+        match chunk {
+            Chunk::Layer(_) | Chunk::Slice(_) | Chunk::Pallette{..} => Some(UserDataTarget::Chunk(index)),
+            Chunk::FrameTags{..} => Some(UserDataTarget::Tag{chunk: index, tag: 0}),
+            Chunk::Tileset(_) => Some(UserDataTarget::Tileset{chunk: index, next: 0}),
+            _ => None,
         }
     }
 }
 
 #[derive(Debug)]
 pub enum Chunk {
-    OldPallette,
-    OtherOldPallette,
+    OldPallette{
+        packets: Vec<OldPallettePacket>,
+    },
+    // same layout as OldPallette, but color components only range from 0 to 63.
+    OtherOldPallette{
+        packets: Vec<OldPallettePacket>,
+    },
     Layer(Layer),
     Cel(Cel),
     CelExtra{
@@ -124,139 +322,219 @@ pub enum Chunk {
         mask_name: String,
         data: Vec<u8>,
     },
-    FrameTags,
+    FrameTags{
+        tags: Vec<Tag>,
+    },
     Pallette{
         size: u32,
         first_color_index: u32,
         last_color_index: u32,
         entries: Vec<PalletteEntry>,
+        // in the first frame this is the sprite's own user data.
+        user_data: Option<UserData>,
     },
-    Slice{
-        key_count: u32,
-        flags: u32,
-        name: String,
-        keys: Vec<SliceKey>,
+    Slice(Slice),
+    Tileset(Tileset),
+    ExternalFiles{
+        files: Vec<ExternalFile>,
     },
     Path,
+    // only kept around when there was nothing to attach it to.
+    UserData(UserData),
+    // a chunk type we don't know how to read, kept as is.
+    Unknown{
+        chunk_type: u16,
+        data: Vec<u8>,
+    },
 }
 
 impl Chunk {
-    fn new_layer(raw: &[u8]) -> Chunk {
-        let (name, _) = read_string(&raw[16..]);
-        let layer = Layer{
-            flags: read_word(&raw[0..]),
-            layer_type: LayerType::from(read_word(&raw[2..])),
-            child_level: read_word(&raw[4..]),
-            default_width: read_word(&raw[6..]),
-            default_height: read_word(&raw[8..]),
-            blend_mode: read_word(&raw[10..]),
-            opacity: raw[12],
-            // 3 unused bytes
-            name,
-            cels: Vec::new(),
-        };
+    fn new_old_pallette_packets(raw: Raw) -> Result<Vec<OldPallettePacket>> {
+        let count = raw.word(0)?;
+        let mut packets = Vec::with_capacity(count as usize);
+        let mut offset = 2;
+        for _ in 0..count {
+            let (packet, size) = OldPallettePacket::new(raw.rest(offset)?)?;
+            offset += size;
+            packets.push(packet);
+        }
 
-        Chunk::Layer(layer)
+        Ok(packets)
     }
 
-    fn new_color_profile(raw: &[u8]) -> Chunk {
-        Chunk::ColorProfile{
-            profile_type: read_word(&raw[0..]),
-            flags: read_word(&raw[2..]),
-            gamma: read_fixed(&raw[4..]),
-            // TODO (erik): Parse ICC data.
-            icc_size: 0,
-            icc_data: Vec::new(),
-        }
+    fn new_layer(raw: Raw) -> Result<Chunk> {
+        Ok(Chunk::Layer(Layer::new(raw)?))
+    }
+
+    fn new_color_profile(raw: Raw) -> Result<Chunk> {
+        let profile_type = raw.word(0)?;
+        color_profile::validate_profile_type(profile_type)
+            .map_err(|value| raw.unknown(0, "color profile type", value))?;
+
+        // 8 reserved bytes
+        let (icc_size, icc_data) = if profile_type == 2 {
+            let icc_size = raw.dword(16)?;
+            (icc_size, Vec::from(raw.get(20, icc_size as usize)?))
+        } else {
+            (0, Vec::new())
+        };
+
+        Ok(Chunk::ColorProfile{
+            profile_type,
+            flags: raw.word(2)?,
+            gamma: raw.fixed(4)?,
+            icc_size,
+            icc_data,
+        })
     }
 
-    fn new_mask(raw: &[u8]) -> Chunk {
-        let width = read_word(&raw[4..]);
-        let height = read_word(&raw[6..]);
-        let (mask_name, offset) = read_string(&raw[8..]);
-        let data_size = (height * ((width + 7)/8)) as usize;
-        Chunk::Mask{
-            x: read_short(&raw[0..]),
-            y: read_short(&raw[2..]),
+    fn new_mask(raw: Raw) -> Result<Chunk> {
+        let width = raw.word(4)?;
+        let height = raw.word(6)?;
+        let (mask_name, offset) = raw.string(8)?;
+        let data_size = height as usize * (width as usize).div_ceil(8);
+        Ok(Chunk::Mask{
+            x: raw.short(0)?,
+            y: raw.short(2)?,
             width,
             height,
             mask_name,
-            data: Vec::from(&raw[10 + offset..10 + offset + data_size]),
-        }
+            data: Vec::from(raw.get(8 + offset, data_size)?),
+        })
     }
 
-    fn new_cel(header: &Header, raw: &[u8]) -> Chunk {
-        Chunk::Cel(Cel::new(header, &raw))
+    fn new_cel(header: &Header, raw: Raw) -> Result<Chunk> {
+        Ok(Chunk::Cel(Cel::new(header, raw)?))
     }
 
-    fn new_cel_extra(raw: &[u8]) -> Chunk {
-        Chunk::CelExtra{
-            flags: read_dword(&raw[0..]),
-            x: read_fixed(&raw[4..]),
-            y: read_fixed(&raw[8..]),
-            width: read_fixed(&raw[12..]),
-            height: read_fixed(&raw[16..])
+    fn new_cel_extra(raw: Raw) -> Result<Chunk> {
+        Ok(Chunk::CelExtra{
+            flags: raw.dword(0)?,
+            x: raw.fixed(4)?,
+            y: raw.fixed(8)?,
+            width: raw.fixed(12)?,
+            height: raw.fixed(16)?,
+        })
+    }
+
+    fn new_frame_tags(raw: Raw) -> Result<Chunk> {
+        let count = raw.word(0)?;
+        let mut tags = Vec::with_capacity(count as usize);
+        let mut offset = 10; // 8 reserved bytes
+        for _ in 0..count {
+            let (tag, size) = Tag::new(raw.rest(offset)?)?;
+            offset += size;
+            tags.push(tag);
         }
+
+        Ok(Chunk::FrameTags{tags})
     }
 
-    fn new_pallette(raw: &[u8]) -> Chunk {
-        Chunk::Pallette{
-            size: read_dword(&raw[0..]),
-            first_color_index: read_dword(&raw[4..]),
-            last_color_index: read_dword(&raw[8..]),
-            // TODO (erik): Parse entries
-            entries: Vec::new(),
+    fn new_pallette(raw: Raw) -> Result<Chunk> {
+        let size = raw.dword(0)?;
+        if size > MAX_PALETTE_SIZE {
+            return Err(raw.unknown(0, "palette size", size));
+        }
+
+        let first_color_index = raw.dword(4)?;
+        let last_color_index = raw.dword(8)?;
+        if last_color_index < first_color_index || last_color_index >= size {
+            return Err(raw.invalid(4, "palette range doesn't fit in the palette"));
+        }
+
+        let count = (last_color_index - first_color_index) as usize + 1;
+        // every entry takes at least 6 bytes
+        let mut entries = Vec::with_capacity(raw.check_count(20, count, 6)?);
+        let mut offset = 20; // 8 reserved bytes
+        for _ in 0..count {
+            let (entry, size) = PalletteEntry::new(raw.rest(offset)?)?;
+            offset += size;
+            entries.push(entry);
         }
+
+        Ok(Chunk::Pallette{
+            size,
+            first_color_index,
+            last_color_index,
+            entries,
+            user_data: None,
+        })
     }
 
-    fn new_slice(raw: &[u8]) -> Chunk {
-        let (name, _) = read_string(&raw[8..]);
-        Chunk::Slice{
-            key_count: read_dword(&raw[0..]),
-            flags: read_dword(&raw[4..]),
-            name: name,
-            // TODO (erik): Parse keys.
-            keys: Vec::new(),
+    fn new_external_files(raw: Raw) -> Result<Chunk> {
+        let count = raw.dword(0)?;
+        let mut offset = 12; // 8 reserved bytes
+        // every entry takes at least 14 bytes
+        let mut files = Vec::with_capacity(raw.check_count(offset, count as usize, 14)?);
+        for _ in 0..count {
+            let (file, size) = ExternalFile::new(raw.rest(offset)?)?;
+            offset += size;
+            files.push(file);
         }
+
+        Ok(Chunk::ExternalFiles{files})
+    }
+
+    fn new_slice(raw: Raw) -> Result<Chunk> {
+        Ok(Chunk::Slice(Slice::new(raw)?))
+    }
+
+    pub fn new(header: &Header, raw: &[u8]) -> Result<(Chunk, u32)> {
+        Chunk::parse(header, Raw::new(raw))
     }
 
-    pub fn new(header: &Header, raw: &[u8]) -> (Chunk, u32) {
-        let size = read_dword(&raw[0..]);
-        let chunk_type = read_word(&raw[4..]);
+    fn parse(header: &Header, raw: Raw) -> Result<(Chunk, u32)> {
+        let size = raw.dword(0)?;
+        if (size as usize) < CHUNK_HEADER_SIZE {
+            return Err(raw.truncated(0, CHUNK_HEADER_SIZE));
+        }
 
-        (match chunk_type {
-            0x0004 => Chunk::OldPallette,
-            0x0011 => Chunk::OtherOldPallette,
-            0x2004 => Chunk::new_layer(&raw[6..]),
-            0x2005 => Chunk::new_cel(header, &raw[6..size as usize]),
-            0x2006 => Chunk::new_cel_extra(&raw[6..]),
-            0x2007 => Chunk::new_color_profile(&raw[6..]),
-            0x2016 => Chunk::new_mask(&raw[6..]),
+        let chunk_type = raw.word(4)?;
+        let body = raw.slice(CHUNK_HEADER_SIZE, size as usize - CHUNK_HEADER_SIZE)?;
+
+        let chunk = match chunk_type {
+            0x0004 => Chunk::OldPallette{packets: Chunk::new_old_pallette_packets(body)?},
+            0x0011 => Chunk::OtherOldPallette{packets: Chunk::new_old_pallette_packets(body)?},
+            0x2004 => Chunk::new_layer(body)?,
+            0x2005 => Chunk::new_cel(header, body)?,
+            0x2006 => Chunk::new_cel_extra(body)?,
+            0x2007 => Chunk::new_color_profile(body)?,
+            0x2008 => Chunk::new_external_files(body)?,
+            0x2016 => Chunk::new_mask(body)?,
             0x2017 => Chunk::Path,
-            0x2018 => Chunk::FrameTags,
-            0x2019 => Chunk::new_pallette(&raw[6..]),
-            0x2022 => Chunk::new_slice(&raw[6..]),
-            _ => panic!("Invalid chunk type!"),
-        }, size)
+            0x2018 => Chunk::new_frame_tags(body)?,
+            0x2019 => Chunk::new_pallette(body)?,
+            0x2020 => Chunk::UserData(UserData::new(body)?),
+            0x2022 => Chunk::new_slice(body)?,
+            0x2023 => Chunk::Tileset(Tileset::new(&header.color_depth, body)?),
+            _ => Chunk::Unknown{chunk_type, data: body.bytes.to_vec()},
+        };
+
+        Ok((chunk, size))
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CelBase {
     layer_index: u16,
     x: i16,
     y: i16,
     opacity: u8,
+    z_index: i16,
+    user_data: Option<UserData>,
 }
 
 impl CelBase {
-    fn new(raw: &[u8]) -> CelBase {
-        CelBase{
-            layer_index: read_word(&raw[0..]),
-            x: read_short(&raw[2..]),
-            y: read_short(&raw[4..]),
-            opacity: raw[6],
-        }
+    fn new(raw: Raw) -> Result<CelBase> {
+        Ok(CelBase{
+            layer_index: raw.word(0)?,
+            x: raw.short(2)?,
+            y: raw.short(4)?,
+            opacity: raw.byte(6)?,
+            // the cel type sits in between
+            z_index: raw.short(9)?,
+            user_data: None,
+        })
     }
 
     fn offset() -> usize {
@@ -264,50 +542,67 @@ impl CelBase {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RawCel {
     base: CelBase,
     width: u16,
     height: u16,
-    pixels: Vec<Pixel>,
+    pixels: Pixels,
 }
 
 impl RawCel {
-    fn new(color_depth: &ColorDepth, raw: &[u8]) -> RawCel {
-        let offset = CelBase::offset() + 9; // 7 for unused bytes, 2 for cel_type
-        let width = read_word(&raw[offset..]) + 1;
-        let height = read_word(&raw[offset+2..]) + 1;
-
-        RawCel{
-            base: CelBase::new(raw),
+    fn new(color_depth: &ColorDepth, raw: Raw) -> Result<RawCel> {
+        let offset = CelBase::offset() + 9; // 2 for cel_type, 2 for z_index, 5 reserved
+        let width = raw.word(offset)?;
+        let height = raw.word(offset+2)?;
+        let (w, h) = (width as usize, height as usize);
+        let data = raw.get(offset+4, color_depth.data_size(w, h))?;
+
+        Ok(RawCel{
+            base: CelBase::new(raw)?,
             width,
             height,
-            pixels: Pixel::new_pixels(color_depth, width, height, &raw[offset+4..]),
-        }
+            pixels: Pixels::new(*color_depth, w, h, data.to_vec()),
+        })
     }
 
-    fn from_compressed(color_depth: &ColorDepth, raw: &[u8]) -> RawCel {
-        let offset = CelBase::offset() + 9; // 7 for unused bytes, 2 for cel_type
-        let width = read_word(&raw[offset..]);
-        let height = read_word(&raw[offset+2..]);
-        let mut data = Vec::with_capacity((width * height) as usize * color_depth.offset());
-        ZlibDecoder::new(&raw[offset+4..]).read_to_end(&mut data).unwrap();
+    // maps the cel's rows onto a canvas of the given size, since RawCel pixel data is often
+    // smaller than the canvas and can sit anywhere on it, even partially outside. Yields the
+    // part of each row that lands on the canvas as (cel pixel index, canvas pixel index, length).
+    fn visible_runs(&self, width: usize, height: usize) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+        let left = (self.base.x as i64).max(0);
+        let right = (self.base.x as i64 + self.width as i64).min(width as i64);
+        let len = (right - left).max(0) as usize;
+        let skipped = (left - self.base.x as i64) as usize;
+        (0..self.height as usize).filter_map(move |row| {
+            let y = self.base.y as i64 + row as i64;
+            if len == 0 || y < 0 || y >= height as i64 {
+                return None;
+            }
 
-        RawCel{
-            base: CelBase::new(raw),
-            width,
-            height,
-            pixels: Pixel::new_pixels(color_depth, width, height, &data),
-        }
+            Some((row * self.width as usize + skipped, y as usize * width + left as usize, len))
+        })
+    }
+
+    // whether part of the cel falls outside a canvas of the given size.
+    fn is_clipped(&self, width: usize, height: usize) -> bool {
+        self.base.x < 0
+            || self.base.y < 0
+            || self.base.x as usize + self.width as usize > width
+            || self.base.y as usize + self.height as usize > height
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
     }
 
-    // maps a pixel index to a target width, since RawCel pixel data is often smaller than the
-    // canvas dimensions represented in the Header.
-    fn map_pixel(&self, idx: usize, width: usize) -> usize {
-        let row = idx / (self.width as usize);
-        let col = idx % (self.width as usize);
+    pub fn height(&self) -> u16 {
+        self.height
+    }
 
-        (row * width) + (col + self.base.x as usize)
+    /// The cel's pixels, row by row from top to bottom.
+    pub fn pixels(&self) -> &Pixels {
+        &self.pixels
     }
 }
 
@@ -318,36 +613,95 @@ pub struct LinkedCel {
 }
 
 impl LinkedCel {
-    fn new(raw: &[u8]) -> LinkedCel {
-        LinkedCel{
-            base: CelBase::new(raw),
-            frame_position: read_word(&raw[CelBase::offset()..])
-        }
+    fn new(raw: Raw) -> Result<LinkedCel> {
+        Ok(LinkedCel{
+            base: CelBase::new(raw)?,
+            frame_position: raw.word(CelBase::offset() + 9)?, // 2 for cel_type, 2 for z_index, 5 reserved
+        })
+    }
+
+    /// The index of the frame holding the cel this one is linked to.
+    pub fn frame_position(&self) -> u16 {
+        self.frame_position
     }
 }
 
-// unused
+/// A cel whose pixels are stored compressed. They stay compressed until they're first asked for,
+/// so reading only a sprite's metadata never pays for decompression.
 #[derive(Debug)]
 pub struct CompressedCel {
     base: CelBase,
     width: u16,
     height: u16,
+    color_depth: ColorDepth,
+    // where the compressed data starts in the file, so errors can point at it.
+    offset: usize,
     data: Vec<u8>, // ZLIB compressed data
+    decoded: OnceLock<RawCel>,
 }
 
 impl CompressedCel {
-    fn new(raw: &[u8]) -> CompressedCel {
-        let offset = CelBase::offset();
-        let width = read_word(&raw[offset..]);
-        let height = read_word(&raw[offset+2..]);
-
-        CompressedCel{
-            base: CelBase::new(raw),
+    fn new(color_depth: &ColorDepth, raw: Raw) -> Result<CompressedCel> {
+        let offset = CelBase::offset() + 9; // 2 for cel_type, 2 for z_index, 5 reserved
+        let width = raw.word(offset)?;
+        let height = raw.word(offset+2)?;
+        let data = raw.rest(offset+4)?;
+
+        Ok(CompressedCel{
+            base: CelBase::new(raw)?,
             width,
             height,
-            data: Vec::from(&raw[offset+4..]),
+            color_depth: *color_depth,
+            offset: data.offset,
+            data: Vec::from(data.bytes),
+            decoded: OnceLock::new(),
+        })
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// The ZLIB compressed pixel data exactly as it was read, for writing the cel back out
+    /// without recompressing it.
+    pub fn compressed_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Whether the pixels have been decompressed yet.
+    pub fn is_decoded(&self) -> bool {
+        self.decoded.get().is_some()
+    }
+
+    /// Returns the cel with its pixels inflated. The data is only decompressed on the first call,
+    /// later calls return the same cel. Data that fails to decompress is tried again next time.
+    pub fn decode(&self) -> Result<&RawCel> {
+        if let Some(cel) = self.decoded.get() {
+            return Ok(cel);
         }
 
+        let cel = self.inflate()?;
+        Ok(self.decoded.get_or_init(|| cel))
+    }
+
+    fn inflate(&self) -> Result<RawCel> {
+        let raw = Raw{
+            bytes: &self.data,
+            offset: self.offset,
+        };
+
+        let (width, height) = (self.width as usize, self.height as usize);
+        let data = raw.inflate(0, self.color_depth.data_size(width, height))?;
+        Ok(RawCel{
+            base: self.base.clone(),
+            width: self.width,
+            height: self.height,
+            pixels: Pixels::new(self.color_depth, width, height, data),
+        })
     }
 }
 
@@ -355,70 +709,58 @@ impl CompressedCel {
 pub enum Cel {
     Raw(RawCel),
     Linked(LinkedCel),
-    Compressed(CompressedCel)
+    Compressed(CompressedCel),
+    Tilemap(TilemapCel),
 }
 
 impl Cel {
-    // fn new_raw(color_depth: &ColorDepth, raw: &[u8]) -> Cel {
-    //     let width = read_word(&raw[0..]);
-    //     let height = read_word(&raw[2..]);
-
-    //     Cel::Raw{
-    //         width,
-    //         height,
-    //         pixels: Pixel::new_pixels(&color_depth, width, height, raw),
-    //     }
-    // }
-
-    // fn new_linked(raw: &[u8]) -> Cel {
-    //     Cel::Linked{
-    //         frame_position: read_word(&raw[0..]),
-    //     }
-    // }
-
-    // fn new_compressed(color_depth: &ColorDepth, raw: &[u8]) -> Cel {
-    //     let width =  read_word(&raw[0..]);
-    //     let height = read_word(&raw[2..]);
-    //     let mut data = Vec::with_capacity((width * height) as usize * color_depth.offset());
-    //     ZlibDecoder::new(&raw[4..]).read_to_end(&mut data).unwrap();
-
-    //     // TODO (erik): Is returning a decompressed, raw cel acceptable behaviour when we find it
-    //     // compressed?
-    //     Cel::Raw{
-    //         width,
-    //         height,
-    //         pixels: Pixel::new_pixels(&color_depth, width, height, &data),
-    //     }
-    //     // Cel::Compressed{
-    //     //     width,
-    //     //     height,
-    //     //     data,
-    //     // }
-    // }
-
-    fn new(header: &Header, raw: &[u8]) -> Cel {
-        // NOTE: Compressed Cels actually get returned as their decompressed Raw counterpart. If
-        // actual compressed cels are needed, this match needs changed.
-        let cel_type = read_word(&raw[7..]);
+    fn new(header: &Header, raw: Raw) -> Result<Cel> {
+        let cel_type = raw.word(7)?;
         match cel_type {
-            0 => Cel::Raw(RawCel::new(&header.color_depth, raw)),
-            1 => Cel::Linked(LinkedCel::new(raw)),
-            2 => Cel::Raw(RawCel::from_compressed(&header.color_depth, raw)),
-            _ => panic!("Invalid cel type!"),
+            0 => Ok(Cel::Raw(RawCel::new(&header.color_depth, raw)?)),
+            1 => Ok(Cel::Linked(LinkedCel::new(raw)?)),
+            2 => Ok(Cel::Compressed(CompressedCel::new(&header.color_depth, raw)?)),
+            3 => Ok(Cel::Tilemap(TilemapCel::new(raw)?)),
+            _ => Err(raw.unknown(7, "cel type", cel_type)),
+        }
+    }
+
+    fn base(&self) -> &CelBase {
+        match self {
+            Cel::Raw(c) => &c.base,
+            Cel::Linked(c) => &c.base,
+            Cel::Compressed(c) => &c.base,
+            Cel::Tilemap(c) => &c.base,
         }
     }
 
-    fn layer_index(&self) -> u16 {
+    fn base_mut(&mut self) -> &mut CelBase {
         match self {
-            Cel::Raw(c) => c.base.layer_index,
-            Cel::Linked(c) => c.base.layer_index,
-            Cel::Compressed(c) => c.base.layer_index,
+            Cel::Raw(c) => &mut c.base,
+            Cel::Linked(c) => &mut c.base,
+            Cel::Compressed(c) => &mut c.base,
+            Cel::Tilemap(c) => &mut c.base,
         }
     }
+
+    pub fn layer_index(&self) -> u16 {
+        self.base().layer_index
+    }
+
+    /// Moves the cel up or down among the layers of its frame when Aseprite draws it: 1 puts it
+    /// above the next layer up, -1 below the next one down.
+    pub fn z_index(&self) -> i16 {
+        self.base().z_index
+    }
+
+    pub fn user_data(&self) -> Option<&UserData> {
+        self.base().user_data.as_ref()
+    }
 }
 
-#[derive(Debug)]
-enum ColorDepth {
+/// How many bits each pixel takes, which decides how pixel data is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
     RGBA,
     GrayScale,
     Indexed,
@@ -430,13 +772,15 @@ impl Default for ColorDepth {
     }
 }
 
-impl From<u16> for ColorDepth {
-    fn from(word: u16) -> ColorDepth {
+impl TryFrom<u16> for ColorDepth {
+    type Error = u16;
+
+    fn try_from(word: u16) -> std::result::Result<ColorDepth, u16> {
         match word {
-            32 => ColorDepth::RGBA,
-            16 => ColorDepth::GrayScale,
-            8 => ColorDepth::Indexed,
-            _ => panic!("Invalid color depth!"),
+            32 => Ok(ColorDepth::RGBA),
+            16 => Ok(ColorDepth::GrayScale),
+            8 => Ok(ColorDepth::Indexed),
+            _ => Err(word),
         }
     }
 }
@@ -449,123 +793,43 @@ impl ColorDepth {
             ColorDepth::Indexed => 1,
         }
     }
-}
-
-pub struct RGBA {
-    r: u8,
-    g: u8,
-    b: u8,
-    a: u8,
-}
-
-impl RGBA {
-    fn new(raw: &[u8]) -> RGBA {
-        RGBA{
-            r: raw[0],
-            g: raw[1],
-            b: raw[2],
-            a: raw[3],
-        }
-    }
-}
-
-impl RGBA {
-    fn overlay(&self, overlay: &RGBA, opacity: f32) -> RGBA {
-        let overlay_alpha = (overlay.a as f32) / 255.0 * opacity;
-        let alpha = (self.a as f32) / 255.0;
-
-        let a = (1.0 - overlay_alpha) * alpha + overlay_alpha;
-        let r = (((1.0 - overlay_alpha) * alpha * (self.r as f32) + overlay_alpha * overlay.r as f32)/a) as u8;
-        let g = (((1.0 - overlay_alpha) * alpha * (self.g as f32) + overlay_alpha * overlay.g as f32)/a) as u8;
-        let b = (((1.0 - overlay_alpha) * alpha * (self.b as f32) + overlay_alpha * overlay.b as f32)/a) as u8;
-
-        RGBA{
-            r,
-            g,
-            b,
-            a: a as u8,
-        }
-    }
-}
 
-// #[derive(Debug)]
-pub enum Pixel {
-    RGBA(RGBA),
-    GrayScale{
-        value: u8,
-        alpha: u8,
-    },
-    Indexed{
-        index: u8,
+    // the number of bytes needed to hold a width x height block of pixels.
+    fn data_size(&self, width: usize, height: usize) -> usize {
+        width * height * self.offset()
     }
 }
 
-impl fmt::Debug for Pixel {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "")
-    }
+#[derive(Debug)]
+pub struct OldPallettePacket {
+    /// How many palette entries to skip before applying this packet's colors.
+    pub entries_to_skip: u8,
+    pub colors: Vec<[u8; 3]>,
 }
 
-impl Pixel {
-    fn new_rgba(raw: &[u8]) -> Pixel {
-        Pixel::RGBA(RGBA::new(raw))
-    }
-
-    fn new_gray_scale(raw: &[u8]) -> Pixel {
-        Pixel::GrayScale{
-            value: raw[0],
-            alpha: raw[1],
-        }
-    }
-
-    fn new_indexed(raw: &[u8]) -> Pixel {
-        Pixel::Indexed{
-            index: raw[0],
-        }
-    }
+impl OldPallettePacket {
+    // returns the packet along with the number of bytes it occupied.
+    fn new(raw: Raw) -> Result<(OldPallettePacket, usize)> {
+        let count = match raw.byte(1)? {
+            0 => 256,
+            count => count as usize,
+        };
 
-    fn new(color_depth: &ColorDepth, raw: &[u8]) -> Pixel {
-        match color_depth {
-            ColorDepth::RGBA => Pixel::RGBA(RGBA::new(raw)),
-            ColorDepth::GrayScale => Pixel::new_gray_scale( raw),
-            ColorDepth::Indexed => Pixel::new_indexed(raw),
-        }
-    }
+        let colors = raw.get(2, count * 3)?
+            .chunks(3)
+            .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+            .collect();
 
-    fn new_pixels(color_depth: &ColorDepth, width: u16, height: u16, raw: &[u8]) -> Vec<Pixel> {
-        let pixel_fn = match color_depth {
-            ColorDepth::RGBA => Pixel::new_rgba,
-            ColorDepth::GrayScale => Pixel::new_gray_scale,
-            ColorDepth::Indexed => Pixel::new_indexed,
+        let packet = OldPallettePacket{
+            entries_to_skip: raw.byte(0)?,
+            colors,
         };
 
-        let mut pixels = Vec::new();
-        let mut offset = 0;
-        for _ in 0..(width * height) {
-            pixels.push(pixel_fn(&raw[offset..]));
-            offset += color_depth.offset();
-        }
-
-        pixels
+        Ok((packet, 2 + count * 3))
     }
 }
 
-#[derive(Debug)]
-pub struct SliceKey {
-    pub frame_number: u32,
-    pub x: i64,
-    pub y: i64,
-    pub width: u32,
-    pub height: u32,
-    pub center_x: i64,
-    pub center_y: i64,
-    pub center_width: u32,
-    pub center_height: u32,
-    pub pivot_x: i64,
-    pub pivot_y: i64,
-}
-
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct PalletteEntry {
     pub flags: u16,
     pub red: u8,
@@ -575,188 +839,1777 @@ pub struct PalletteEntry {
     pub color_name: String,
 }
 
+impl PalletteEntry {
+    // returns the entry along with the number of bytes it occupied.
+    fn new(raw: Raw) -> Result<(PalletteEntry, usize)> {
+        let flags = raw.word(0)?;
+        let (color_name, name_size) = if flags & 1 != 0 {
+            raw.string(6)?
+        } else {
+            (String::new(), 0)
+        };
+
+        let entry = PalletteEntry{
+            flags,
+            red: raw.byte(2)?,
+            green: raw.byte(3)?,
+            blue: raw.byte(4)?,
+            alpha: raw.byte(5)?,
+            color_name,
+        };
+
+        Ok((entry, 6 + name_size))
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct Ase {
     pub header: Header,
     pub frames: Vec<Frame>,
+    layers: Vec<Layer>,
+    palette: Palette,
+    tags: Vec<Tag>,
+    slices: Vec<Slice>,
+    color_profile: ColorProfile,
+    tilesets: Vec<Tileset>,
+    external_files: Vec<ExternalFile>,
+    diagnostics: Diagnostics,
 }
 
 impl Ase {
-    pub fn new(raw: &[u8]) -> Ase {
-        let header = Header::new(raw);
+    pub fn new(raw: &[u8]) -> Result<Ase> {
+        let raw = Raw::new(raw);
+        let header = Header::parse(raw)?;
         let mut frames = Vec::new();
+        let mut diagnostics = Diagnostics::default();
         let mut offset = HEADER_SIZE;
         for _ in 0..header.frames {
-            let frame = Frame::new(&header, &raw[offset..]);
+            let frame = Frame::parse(&header, raw.rest(offset)?, &mut diagnostics)?;
             offset += frame.size as usize;
             frames.push(frame);
         }
 
-        Ase{
-            header,
-            frames,
-        }
+        Ok(Ase::from_frames(header, frames, diagnostics))
+    }
 
+    /// Parses a sprite from a reader without loading the whole file first. Only one chunk is
+    /// held in memory at a time while reading.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Ase> {
+        reader::read_ase(reader)
     }
 
-    /// Renders the Ase structure into an array of pixel values. The final format of this data
-    /// depends on the color depth defined in the Header.
-    ///
-    /// A render is generated by iterating over the cel chunks in each layer and applying the color
-    /// data with the configured opacity. If there are multiple frames, this procedure is repeated
-    /// for each frame generating a strip of frame data.
-    pub fn render(&self) -> Vec<u8> {
-        let width = self.header.width as usize;
-        let height = self.header.height as usize;
-        let color_depth = &self.header.color_depth;
-        println!("Header width: {}, Header height: {}", width, height);
-        let mut image_data: Vec<u8> = vec![0; width * height * color_depth.offset()];
-        println!("Image data length: {}", image_data.len());
-
-        for frame in &self.frames {
-            for layer in &frame.layers {
-                let layer_opacity = (layer.opacity as f32) / 255.0;
-                for cel in &layer.cels {
-                    match cel {
-                        Cel::Raw(c) => {
-                            let opacity = (c.base.opacity as f32) / 255.0 * layer_opacity;
-                            println!("Opacity: {}", opacity);
-                            for (i, pixel) in c.pixels.iter().enumerate() {
-                                match pixel {
-                                    Pixel::RGBA(p) => {
-                                        let idx = c.map_pixel(i, width) * color_depth.offset();
-                                        let src = RGBA::new(&image_data[idx..]);
-
-                                        let overlayed = src.overlay(p, opacity);
-                                        image_data[idx] = overlayed.r;
-                                        image_data[idx+1] = overlayed.g;
-                                        image_data[idx+2] = overlayed.b;
-                                        image_data[idx+3] = overlayed.a;
-                                    },
-                                    Pixel::GrayScale{value, alpha} => {
-                                        image_data.push(*value);
-                                        image_data.push(*alpha);
-                                    },
-                                    Pixel::Indexed{index} => image_data.push(*index),
-                                }
-                            }
-                            println!("Found a raw cel!")
-                        }
-                        _ => println!("Non pixel cel"),
-                    }
-                }
-            }
-        }
+    /// Opens and parses the sprite at the given path.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Ase> {
+        let file = File::open(path).map_err(|source| AseError::Io{
+            offset: 0,
+            source,
+        })?;
 
-        image_data
+        Ase::from_reader(BufReader::new(file))
     }
-}
 
-fn overlay(c1: u8, c2: u8, opacity: f32) -> u8 {
-    ((1.0 - opacity)*(c1 as f32) + (opacity*(c2 as f32))) as u8
-}
+    /// Writes the sprite out as an aseprite file. Raw cels are compressed on the way out, while
+    /// cels that were read compressed keep their original data.
+    pub fn write_to<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        writer::write(self, writer)
+    }
 
-impl Header {
-    pub fn new(raw: &[u8]) -> Header {
-        Header{
-            file_size: read_dword(&raw[0..]),
-            magic_number: read_word(&raw[4..]),
-            frames: read_word(&raw[6..]),
-            width: read_word(&raw[8..10]),
-            height: read_word(&raw[10..12]),
-            color_depth: ColorDepth::from(read_word(&raw[12..14])),
-            flags: read_dword(&raw[14..18]),
-            speed: read_word(&raw[18..20]),
-            pallette_entry: raw[24],
-            number_of_colors: read_word(&raw[28..30]),
-            pixel_width: raw[30],
-            pixel_height: raw[31],
+    // pulls everything that describes the whole sprite out of the parsed frames.
+    fn from_frames(header: Header, mut frames: Vec<Frame>, mut diagnostics: Diagnostics) -> Ase {
+        let mut layers = Vec::new();
+        let mut tilesets = Vec::new();
+        for frame in &mut frames {
+            layers.append(&mut frame.take_layers());
+            tilesets.append(&mut frame.take_tilesets());
         }
-    }
-}
 
+        let chunks = || frames.iter().flat_map(|frame| &frame.chunks);
+        let palette = Palette::from_chunks(chunks());
+        let has_new_palette = chunks().any(|chunk| matches!(chunk, Chunk::Pallette{..}));
+        let has_old_palette = chunks().any(|chunk| matches!(chunk, Chunk::OldPallette{..} | Chunk::OtherOldPallette{..}));
+        if has_old_palette && !has_new_palette {
+            diagnostics.warn(Warning::OldPalette);
+        }
 
-fn read_dword(bytes: &[u8]) -> u32 {
-    ((bytes[0] as u32) << 0) +
-    ((bytes[1] as u32) << 8) +
-    ((bytes[2] as u32) << 16) +
-    ((bytes[3] as u32) << 24)
-}
+        let tags = frames.iter()
+            .flat_map(|frame| &frame.chunks)
+            .filter_map(|chunk| match chunk {
+                Chunk::FrameTags{tags} => Some(tags.iter().cloned()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        let slices = frames.iter()
+            .flat_map(|frame| &frame.chunks)
+            .filter_map(|chunk| match chunk {
+                Chunk::Slice(slice) => Some(slice.clone()),
+                _ => None,
+            })
+            .collect();
+        let color_profile = frames.iter()
+            .flat_map(|frame| &frame.chunks)
+            .find_map(ColorProfile::from_chunk)
+            .unwrap_or_default();
+        let external_files = frames.iter()
+            .flat_map(|frame| &frame.chunks)
+            .filter_map(|chunk| match chunk {
+                Chunk::ExternalFiles{files} => Some(files.iter().cloned()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
 
-fn read_word(bytes: &[u8]) -> u16 {
-    ((bytes[0] as u16) << 0) +
-    ((bytes[1] as u16) << 8)
-}
+        Ase{
+            header,
+            frames,
+            layers,
+            palette,
+            tags,
+            slices,
+            color_profile,
+            tilesets,
+            external_files,
+            diagnostics,
+        }
+    }
 
-fn read_short(bytes: &[u8]) -> i16 {
-    ((bytes[0] as i16) << 0) +
-    ((bytes[1] as i16) << 8)
-}
+    /// All of the sprite's layers, ordered from bottom to top. A cel's layer index points into
+    /// this list.
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
 
-fn read_long(bytes: &[u8]) -> i32 {
-    ((bytes[0] as i32) << 0) +
-    ((bytes[1] as i32) << 8) +
-    ((bytes[2] as i32) << 16) +
-    ((bytes[3] as i32) << 24)
-}
+    /// The sprite's layers arranged into their groups.
+    pub fn layer_tree(&self) -> LayerTree<'_> {
+        LayerTree::new(&self.layers)
+    }
 
-fn read_fixed(bytes: &[u8]) -> Fixed {
-    Fixed::from_bits(read_long(bytes))
-}
+    /// The sprite's palette, with every palette chunk in the file applied in order.
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
 
-fn read_string(bytes: &[u8]) -> (String, usize) {
-    let length = read_word(&bytes[0..]) as usize;
-    // TODO (erik): Maybe make this safe? Not sure what to do with the error, though.
-    let raw_str = unsafe { std::str::from_utf8_unchecked(&bytes[2..length + 2]) };
-    (String::from(raw_str), length + 2)
-}
+    /// The sprite's animation tags in the order they were defined.
+    pub fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use image::png::PNGEncoder;
-    use std::fs;
+    /// Looks up a tag by name.
+    pub fn tag(&self, name: &str) -> Option<&Tag> {
+        self.tags.iter().find(|tag| tag.name == name)
+    }
 
-    #[test]
-    fn it_works() {
-        assert_eq!(2 + 2, 4);
+    /// Every slice defined in the sprite.
+    pub fn slices(&self) -> &[Slice] {
+        &self.slices
     }
 
-    #[test]
-    fn test_read_dword() {
-        let test_bytes: Vec<u8> = vec![1, 0, 0, 0];
-        let dword = read_dword(&test_bytes[0..4]);
-        assert_eq!(dword, 1);
+    /// Looks up a slice by name.
+    pub fn slice(&self, name: &str) -> Option<&Slice> {
+        self.slices.iter().find(|slice| slice.name == name)
     }
 
-    #[test]
-    fn test_read_word() {
-        let one: Vec<u8> = vec![1, 0];
-        let one_word = read_word(&one[0..2]);
-        let two_fifty_six: Vec<u8> = vec![0, 1];
-        let two_fifty_six_word = read_word(&two_fifty_six[0..2]);
-        assert_eq!(one_word, 1);
-        assert_eq!(two_fifty_six_word, 256);
+    /// Every tileset in the sprite. Tilemap layers refer to these by index.
+    pub fn tilesets(&self) -> &[Tileset] {
+        &self.tilesets
     }
 
-    #[test]
-    fn test_new_header() {
-        let test_bytes = include_bytes!("../test.ase");
-        let header = Header::new(test_bytes);
-        println!("{:?}", header);
+    /// Everything odd about the file that was noticed while parsing it.
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
     }
 
-    #[test]
-    fn test_read_file() {
-        let test_bytes = include_bytes!("../test.ase");
-        let ase = Ase::new(test_bytes);
-        println!("{:?}", ase);
+    /// The palettes, tilesets and extensions the sprite refers to in other files.
+    pub fn external_files(&self) -> &[ExternalFile] {
+        &self.external_files
     }
 
-    #[test]
-    fn test_render_image() {
+    /// Loads the external palette and tilesets the sprite refers to through the resolver, and
+    /// links them in: tilesets stored in another file get their tiles filled in, and a sprite
+    /// without a palette of its own takes the external one. Use a FileSystemResolver to load
+    /// them from next to the sprite.
+    pub fn resolve_external_files<R: ExternalFileResolver>(&mut self, resolver: &mut R) -> Result<()> {
+        external::resolve(self, resolver)
+    }
+
+    /// The color space the sprite's pixels are in.
+    pub fn color_profile(&self) -> &ColorProfile {
+        &self.color_profile
+    }
+
+    /// Returns the cel at the given frame and layer index, if there is one.
+    pub fn cel(&self, frame_index: usize, layer_index: usize) -> Option<&Cel> {
+        self.frames.get(frame_index).and_then(|frame| frame.cel(layer_index))
+    }
+
+    /// Returns the pixel data for the cel at the given frame and layer index. Linked cels are
+    /// followed back to the cel they share their pixels with, which lives in another frame on the
+    /// same layer, and compressed cels are decompressed. Returns None for cels whose data fails
+    /// to decompress; `CompressedCel::decode` says why. Tilemap cels have no pixels of their own
+    /// and also return None.
+    pub fn resolve_cel(&self, frame_index: usize, layer_index: usize) -> Option<&RawCel> {
+        match self.unlinked_cel(frame_index, layer_index)? {
+            Cel::Raw(c) => Some(c),
+            Cel::Compressed(c) => c.decode().ok(),
+            _ => None,
+        }
+    }
+
+    // follows linked cels back to the cel that holds the data.
+    fn unlinked_cel(&self, frame_index: usize, layer_index: usize) -> Option<&Cel> {
+        let mut frame_index = frame_index;
+        // a link can only point at a cel with data, but a malformed file could point them at
+        // each other forever.
+        for _ in 0..=self.frames.len() {
+            match self.cel(frame_index, layer_index)? {
+                Cel::Linked(c) => frame_index = c.frame_position as usize,
+                cel => return Some(cel),
+            }
+        }
+
+        None
+    }
+
+    /// Renders a single frame by compositing the cels of every visible layer from bottom to top,
+    /// with each layer's blend mode and opacity. Returns None if there's no such frame.
+    pub fn render_frame(&self, frame_index: usize) -> Option<Image> {
+        self.render_with(&RenderOptions::new().frame(frame_index))
+    }
+
+    /// Renders the frame picked by the options with only the layers they pick. Returns None if
+    /// there's no such frame.
+    pub fn render_with(&self, options: &RenderOptions) -> Option<Image> {
+        self.render_with_diagnostics(options).map(|(image, _)| image)
+    }
+
+    /// Same as render_with, along with everything odd that was noticed while rendering.
+    pub fn render_with_diagnostics(&self, options: &RenderOptions) -> Option<(Image, Diagnostics)> {
+        if options.frame < self.frames.len() {
+            let mut diagnostics = Diagnostics::default();
+            let image = render::render_frame(self, options, &mut diagnostics);
+            Some((image, diagnostics))
+        } else {
+            None
+        }
+    }
+
+    /// Renders a frame of an indexed sprite without converting it to RGBA, keeping one palette
+    /// index per pixel. Returns None for other color depths or if there's no such frame.
+    pub fn render_frame_indexed(&self, frame_index: usize) -> Option<IndexedImage> {
+        match self.header.color_depth {
+            ColorDepth::Indexed if frame_index < self.frames.len() => {
+                Some(render::render_frame_indexed(self, frame_index))
+            },
+            _ => None,
+        }
+    }
+
+    /// Renders a frame of a grayscale sprite without converting it to RGBA. Returns None for
+    /// other color depths or if there's no such frame.
+    pub fn render_frame_grayscale(&self, frame_index: usize) -> Option<GrayImage> {
+        match self.header.color_depth {
+            ColorDepth::GrayScale if frame_index < self.frames.len() => {
+                Some(render::render_frame_grayscale(self, frame_index))
+            },
+            _ => None,
+        }
+    }
+
+    /// Renders every frame in order, along with how long each one is shown for.
+    pub fn render_frames(&self) -> impl Iterator<Item = RenderedFrame> + '_ {
+        self.frames.iter().enumerate().map(move |(frame_index, frame)| RenderedFrame{
+            image: self.render_frame(frame_index).unwrap(),
+            duration: frame.frame_duration,
+        })
+    }
+
+    /// Renders the first frame into RGBA pixel data, whatever the sprite's color depth. Use
+    /// render_frame or render_frames for animations.
+    pub fn render(&self) -> Vec<u8> {
+        self.render_frame(0)
+            .map(Image::into_raw)
+            .unwrap_or_default()
+    }
+
+    /// Same as render, but converts the output from the sprite's color profile into sRGB.
+    /// Profiles that can't be understood are left as is. Use `RenderOptions::srgb` to do the same
+    /// for other frames.
+    pub fn render_srgb(&self) -> Vec<u8> {
+        self.render_with(&RenderOptions::new().srgb(true))
+            .map(Image::into_raw)
+            .unwrap_or_default()
+    }
+}
+
+impl Header {
+    pub fn new(raw: &[u8]) -> Result<Header> {
+        Header::parse(Raw::new(raw))
+    }
+
+    /// The palette index that's drawn as transparent in indexed sprites, except on the
+    /// background layer.
+    pub fn transparent_index(&self) -> u8 {
+        self.pallette_entry
+    }
+
+    pub fn color_depth(&self) -> ColorDepth {
+        self.color_depth
+    }
+
+    /// The canvas width in pixels.
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// The canvas height in pixels.
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    pub fn frame_count(&self) -> u16 {
+        self.frames
+    }
+
+    /// Whether the layers' opacity is meant to be used. Files from before Aseprite had layer
+    /// opacity leave this unset and whatever is stored there should be ignored.
+    pub fn has_layer_opacity(&self) -> bool {
+        self.flags & LAYER_OPACITY_FLAG != 0
+    }
+
+    /// Whether groups are drawn as a whole with their own blend mode and opacity. Older files
+    /// leave this unset and draw the layers inside groups straight onto the canvas.
+    pub fn composites_groups(&self) -> bool {
+        self.flags & COMPOSITE_GROUPS_FLAG != 0
+    }
+
+    fn parse(raw: Raw) -> Result<Header> {
+        let raw = raw.slice(0, HEADER_SIZE)?;
+        let magic_number = raw.word(4)?;
+        if magic_number != HEADER_MAGIC {
+            return Err(AseError::BadMagic{
+                offset: raw.offset + 4,
+                expected: HEADER_MAGIC,
+                found: magic_number,
+            });
+        }
+
+        let color_depth = raw.word(12)?;
+        Ok(Header{
+            file_size: raw.dword(0)?,
+            magic_number,
+            frames: raw.word(6)?,
+            width: raw.word(8)?,
+            height: raw.word(10)?,
+            color_depth: ColorDepth::try_from(color_depth)
+                .map_err(|value| raw.unknown(12, "color depth", value))?,
+            flags: raw.dword(14)?,
+            speed: raw.word(18)?,
+            // 8 reserved bytes
+            pallette_entry: raw.byte(28)?,
+            // 3 unused bytes
+            number_of_colors: raw.word(32)?,
+            pixel_width: raw.byte(34)?,
+            pixel_height: raw.byte(35)?,
+        })
+    }
+}
+
+/// A bounds checked view into the file being parsed. It remembers where it starts in the file so
+/// errors can report absolute offsets no matter how deep into a chunk they happen.
+#[derive(Clone, Copy)]
+struct Raw<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Raw<'a> {
+    fn new(bytes: &'a [u8]) -> Raw<'a> {
+        Raw{
+            bytes,
+            offset: 0,
+        }
+    }
+
+    fn truncated(&self, at: usize, needed: usize) -> AseError {
+        AseError::Truncated{
+            offset: self.offset + at,
+            needed,
+            available: self.bytes.len().saturating_sub(at),
+        }
+    }
+
+    // makes sure count items of at least item_size bytes each could fit from at onward, before
+    // anything is allocated for them. Counts read from the file can claim anything.
+    fn check_count(&self, at: usize, count: usize, item_size: usize) -> Result<usize> {
+        let needed = count.saturating_mul(item_size);
+        if at.saturating_add(needed) > self.bytes.len() {
+            return Err(self.truncated(at, needed));
+        }
+
+        Ok(count)
+    }
+
+    fn unknown<T: Into<u32>>(&self, at: usize, kind: &'static str, value: T) -> AseError {
+        AseError::UnknownValue{
+            offset: self.offset + at,
+            kind,
+            value: value.into(),
+        }
+    }
+
+    fn invalid(&self, at: usize, reason: &'static str) -> AseError {
+        AseError::Invalid{
+            offset: self.offset + at,
+            reason,
+        }
+    }
+
+    fn get(&self, at: usize, len: usize) -> Result<&'a [u8]> {
+        at.checked_add(len)
+            .and_then(|end| self.bytes.get(at..end))
+            .ok_or_else(|| self.truncated(at, len))
+    }
+
+    fn slice(&self, at: usize, len: usize) -> Result<Raw<'a>> {
+        Ok(Raw{
+            bytes: self.get(at, len)?,
+            offset: self.offset + at,
+        })
+    }
+
+    fn rest(&self, at: usize) -> Result<Raw<'a>> {
+        self.slice(at, self.bytes.len().saturating_sub(at))
+    }
+
+    fn byte(&self, at: usize) -> Result<u8> {
+        Ok(self.get(at, 1)?[0])
+    }
+
+    fn word(&self, at: usize) -> Result<u16> {
+        Ok(read_word(self.get(at, 2)?))
+    }
+
+    fn short(&self, at: usize) -> Result<i16> {
+        Ok(read_short(self.get(at, 2)?))
+    }
+
+    fn dword(&self, at: usize) -> Result<u32> {
+        Ok(read_dword(self.get(at, 4)?))
+    }
+
+    fn qword(&self, at: usize) -> Result<u64> {
+        let bytes = self.get(at, 8)?;
+        Ok(read_dword(&bytes[0..]) as u64 | (read_dword(&bytes[4..]) as u64) << 32)
+    }
+
+    fn long(&self, at: usize) -> Result<i32> {
+        Ok(read_long(self.get(at, 4)?))
+    }
+
+    fn fixed(&self, at: usize) -> Result<Fixed> {
+        Ok(read_fixed(self.get(at, 4)?))
+    }
+
+    // returns the string along with the number of bytes it occupied.
+    fn string(&self, at: usize) -> Result<(String, usize)> {
+        let length = self.word(at)? as usize;
+        let bytes = self.get(at + 2, length)?;
+        let value = std::str::from_utf8(bytes).map_err(|source| AseError::InvalidUtf8{
+            offset: self.offset + at + 2 + source.valid_up_to(),
+            source,
+        })?;
+
+        Ok((String::from(value), length + 2))
+    }
+
+    // inflates the ZLIB data running from at to the end of the view, which should decompress to
+    // exactly size bytes.
+    fn inflate(&self, at: usize, size: usize) -> Result<Vec<u8>> {
+        let compressed = self.rest(at)?;
+        // size comes from the file, so it only caps how far the data can grow. Reserving it up
+        // front would let a tiny stream claiming a huge image exhaust memory.
+        let mut data = Vec::with_capacity(size.min(compressed.bytes.len()));
+        ZlibDecoder::new(compressed.bytes)
+            .take(size as u64)
+            .read_to_end(&mut data)
+            .map_err(|source| AseError::Decompression{offset: compressed.offset, source})?;
+
+        if data.len() < size {
+            return Err(AseError::Decompression{
+                offset: compressed.offset,
+                source: io::Error::new(io::ErrorKind::UnexpectedEof, format!(
+                    "expected {} bytes of pixel data, found {}", size, data.len()
+                )),
+            });
+        }
+
+        Ok(data)
+    }
+}
+
+
+fn read_dword(bytes: &[u8]) -> u32 {
+    ((bytes[0] as u32) << 0) +
+    ((bytes[1] as u32) << 8) +
+    ((bytes[2] as u32) << 16) +
+    ((bytes[3] as u32) << 24)
+}
+
+fn read_word(bytes: &[u8]) -> u16 {
+    ((bytes[0] as u16) << 0) +
+    ((bytes[1] as u16) << 8)
+}
+
+fn read_short(bytes: &[u8]) -> i16 {
+    ((bytes[0] as i16) << 0) +
+    ((bytes[1] as i16) << 8)
+}
+
+fn read_long(bytes: &[u8]) -> i32 {
+    ((bytes[0] as i32) << 0) +
+    ((bytes[1] as i32) << 8) +
+    ((bytes[2] as i32) << 16) +
+    ((bytes[3] as i32) << 24)
+}
+
+fn read_fixed(bytes: &[u8]) -> Fixed {
+    Fixed::from_bits(read_long(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::png::PNGEncoder;
+    use std::error::Error;
+    use std::fs;
+    use std::io;
+
+    #[test]
+    fn it_works() {
+        assert_eq!(2 + 2, 4);
+    }
+
+    #[test]
+    fn test_read_dword() {
+        let test_bytes: Vec<u8> = vec![1, 0, 0, 0];
+        let dword = read_dword(&test_bytes[0..4]);
+        assert_eq!(dword, 1);
+    }
+
+    #[test]
+    fn test_read_word() {
+        let one: Vec<u8> = vec![1, 0];
+        let one_word = read_word(&one[0..2]);
+        let two_fifty_six: Vec<u8> = vec![0, 1];
+        let two_fifty_six_word = read_word(&two_fifty_six[0..2]);
+        assert_eq!(one_word, 1);
+        assert_eq!(two_fifty_six_word, 256);
+    }
+
+    #[test]
+    fn test_new_header() {
+        let test_bytes = include_bytes!("../test.ase");
+        let header = Header::new(test_bytes).unwrap();
+        println!("{:?}", header);
+        assert_eq!(header.transparent_index(), 0);
+        assert_eq!(header.number_of_colors, 32);
+        assert_eq!((header.pixel_width, header.pixel_height), (1, 1));
+    }
+
+    #[test]
+    fn test_read_file() {
+        let test_bytes = include_bytes!("../test.ase");
+        let ase = Ase::new(test_bytes).unwrap();
+        println!("{:?}", ase);
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        let mut test_bytes = include_bytes!("../test.ase").to_vec();
+        test_bytes[4] = 0;
+        match Ase::new(&test_bytes) {
+            Err(AseError::BadMagic{offset, ..}) => assert_eq!(offset, 4),
+            other => panic!("expected bad magic, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_truncated() {
         let test_bytes = include_bytes!("../test.ase");
-        let ase = Ase::new(test_bytes);
+        match Ase::new(&test_bytes[..500]) {
+            Err(AseError::Truncated{offset, ..}) => assert_eq!(offset, HEADER_SIZE),
+            other => panic!("expected truncated data, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unknown_chunk_type() {
+        let mut test_bytes = include_bytes!("../test.ase").to_vec();
+        // type of the first chunk in the first frame
+        test_bytes[148] = 0x34;
+        test_bytes[149] = 0x12;
+        let ase = Ase::new(&test_bytes).unwrap();
+        assert_eq!(ase.diagnostics().warnings(), [Warning::UnknownChunk{offset: 144, chunk_type: 0x1234}]);
+        assert!(matches!(ase.frames[0].chunks[0], Chunk::Unknown{chunk_type: 0x1234, ..}));
+        assert_eq!(ase.layers().len(), 2);
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let mut test_bytes = include_bytes!("../test.ase").to_vec();
+        // second character of the first layer's name
+        test_bytes[515] = 0xFF;
+        match Ase::new(&test_bytes) {
+            Err(AseError::InvalidUtf8{offset, ..}) => assert_eq!(offset, 515),
+            other => panic!("expected invalid utf8, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_corrupt_cel_data() {
+        let mut test_bytes = include_bytes!("../test.ase").to_vec();
+        // start of the first cel's compressed data
+        for byte in &mut test_bytes[587..595] {
+            *byte = 0xFF;
+        }
+
+        // nothing is decompressed until the pixels are needed
+        let ase = Ase::new(&test_bytes).unwrap();
+        let cel = match ase.cel(0, 0) {
+            Some(Cel::Compressed(cel)) => cel,
+            other => panic!("expected a compressed cel, got {:?}", other),
+        };
+
+        match cel.decode() {
+            Err(AseError::Decompression{offset, ..}) => assert_eq!(offset, 587),
+            other => panic!("expected decompression failure, got {:?}", other),
+        }
+        assert!(ase.resolve_cel(0, 0).is_none());
+    }
+
+    #[test]
+    fn test_huge_compressed_cel() {
+        // claims 65535x65535 pixels but holds almost no data
+        let test_bytes = test_util::file(1, 1, 32, &[
+            test_util::frame(100, &[
+                test_util::layer("Layer", 1, 0, 0, 0, 255),
+                test_util::compressed_cel(0, 0, 0, 65535, 65535, &[]),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        match ase.cel(0, 0) {
+            Some(Cel::Compressed(cel)) => assert!(matches!(cel.decode(), Err(AseError::Decompression{..}))),
+            other => panic!("expected a compressed cel, got {:?}", other),
+        }
+        assert!(ase.resolve_cel(0, 0).is_none());
+    }
+
+    #[test]
+    fn test_cel_grid() {
+        let red = [255, 0, 0, 255];
+        let blue = [0, 0, 255, 255];
+        let test_bytes = test_util::file(2, 2, 32, &[
+            test_util::frame(100, &[
+                test_util::layer("Background", 1, 0, 0, 0, 255),
+                test_util::layer("Foreground", 1, 0, 0, 0, 255),
+                test_util::raw_cel(0, 0, 0, 1, 1, &red),
+            ]),
+            test_util::frame(100, &[
+                test_util::raw_cel(1, 1, 1, 1, 1, &blue),
+                test_util::compressed_cel(0, 0, 0, 1, 1, &blue),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        assert_eq!(ase.layers().len(), 2);
+        assert_eq!(ase.layers()[1].name(), "Foreground");
+
+        assert_eq!(ase.cel(0, 0).unwrap().layer_index(), 0);
+        assert!(ase.cel(0, 1).is_none());
+        assert_eq!(ase.cel(1, 0).unwrap().layer_index(), 0);
+        assert_eq!(ase.cel(1, 1).unwrap().layer_index(), 1);
+        assert!(ase.cel(1, 2).is_none());
+        assert!(ase.cel(2, 0).is_none());
+        assert_eq!(ase.frames[1].cels().count(), 2);
+    }
+
+    #[test]
+    fn test_layers() {
+        let test_bytes = test_util::file(2, 2, 32, &[
+            test_util::frame(100, &[
+                test_util::layer("Background", 1 | 8, 0, 0, 0, 255),
+                test_util::layer("Body", 1 | 32, 1, 0, 0, 255),
+                test_util::layer("Shading", 2 | 0x8000, 0, 1, 18, 128),
+                test_util::layer("Sketch", 1 | 64, 0, 0, 2, 64),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        let layers = ase.layers();
+        assert_eq!(layers[0].flags(), LayerFlags::VISIBLE | LayerFlags::BACKGROUND);
+        assert_eq!(layers[1].layer_type(), LayerType::Group);
+        assert!(layers[1].flags().contains(LayerFlags::COLLAPSED));
+
+        let shading = &layers[2];
+        assert_eq!(shading.name(), "Shading");
+        assert!(!shading.is_visible());
+        assert_eq!(shading.flags(), LayerFlags::EDITABLE);
+        assert_eq!(shading.child_level(), 1);
+        assert_eq!(shading.blend_mode(), BlendMode::Divide);
+        assert_eq!(shading.opacity(), 128);
+
+        assert!(layers[3].flags().contains(LayerFlags::REFERENCE));
+        assert_eq!(layers[3].blend_mode(), BlendMode::Screen);
+
+        let test_bytes = test_util::file(2, 2, 32, &[
+            test_util::frame(100, &[test_util::layer("Bad", 1, 0, 0, 19, 255)]),
+        ]);
+        match Ase::new(&test_bytes).unwrap_err() {
+            AseError::UnknownValue{offset, kind, value} => {
+                assert_eq!((offset, kind, value), (160, "blend mode", 19));
+            },
+            other => panic!("expected unknown blend mode, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_layer_tree() {
+        let test_bytes = test_util::file(2, 2, 32, &[
+            test_util::frame(100, &[
+                test_util::layer("Background", 1, 0, 0, 0, 255),
+                test_util::layer("body", 1, 1, 0, 0, 255),
+                test_util::layer("arm", 0, 1, 1, 0, 255),
+                test_util::layer("left", 1, 0, 2, 0, 255),
+                test_util::layer("right", 1, 0, 2, 0, 255),
+                test_util::layer("torso", 1, 0, 1, 0, 255),
+                test_util::layer("Sky", 1, 0, 0, 0, 255),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        let tree = ase.layer_tree();
+        let roots: Vec<&str> = tree.roots().map(|node| node.layer().name()).collect();
+        assert_eq!(roots, ["Background", "body", "Sky"]);
+        let order: Vec<usize> = tree.iter().map(|node| node.index()).collect();
+        assert_eq!(order, [0, 1, 2, 3, 4, 5, 6]);
+
+        let left = tree.find("body/arm/left").unwrap();
+        assert_eq!(left.index(), 3);
+        assert_eq!(left.parent().unwrap().layer().name(), "arm");
+        assert_eq!(left.path(), "body/arm/left");
+        assert!(left.layer().is_visible());
+        assert!(!left.is_visible());
+
+        let body = tree.find("body").unwrap();
+        assert!(body.is_group());
+        assert!(body.parent().is_none());
+        let children: Vec<usize> = body.children().map(|node| node.index()).collect();
+        assert_eq!(children, [2, 5]);
+        assert!(tree.get(5).unwrap().is_visible());
+        assert_eq!(tree.get(5).unwrap().ancestors().count(), 1);
+
+        assert!(tree.find("body/left").is_none());
+        assert!(tree.find("arm").is_none());
+        assert!(tree.get(7).is_none());
+    }
+
+    #[test]
+    fn test_resolve_linked_cel() {
+        let red = [255, 0, 0, 255];
+        let test_bytes = test_util::file(1, 1, 32, &[
+            test_util::frame(100, &[
+                test_util::layer("Background", 1, 0, 0, 0, 255),
+                test_util::compressed_cel(0, 0, 0, 1, 1, &red),
+            ]),
+            test_util::frame(100, &[test_util::linked_cel(0, 0, 0, 0)]),
+            test_util::frame(100, &[test_util::linked_cel(0, 0, 0, 2)]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        match ase.cel(1, 0) {
+            Some(Cel::Linked(c)) => assert_eq!(c.frame_position(), 0),
+            other => panic!("expected linked cel, got {:?}", other),
+        }
+
+        let source = ase.resolve_cel(0, 0).unwrap();
+        let linked = ase.resolve_cel(1, 0).unwrap();
+        assert!(std::ptr::eq(source, linked));
+        assert_eq!(linked.pixels().len(), 1);
+
+        // a cel linked to itself has no pixels to find
+        assert!(ase.resolve_cel(2, 0).is_none());
+    }
+
+    #[test]
+    fn test_palette() {
+        let test_bytes = include_bytes!("../test.ase");
+        let ase = Ase::new(test_bytes).unwrap();
+        let palette = ase.palette();
+        assert_eq!(palette.len(), 32);
+        assert_eq!((palette[1].red, palette[1].green, palette[1].blue, palette[1].alpha), (34, 32, 52, 255));
+    }
+
+    #[test]
+    fn test_palette_names_and_merging() {
+        let test_bytes = test_util::file(1, 1, 8, &[
+            test_util::frame(100, &[
+                test_util::palette(4, 0, &[
+                    ([0, 0, 0, 0], None),
+                    ([255, 0, 0, 255], Some("red")),
+                    ([0, 255, 0, 255], None),
+                    ([0, 0, 255, 255], Some("blue")),
+                ]),
+                test_util::palette(5, 2, &[
+                    ([255, 255, 255, 255], Some("white")),
+                ]),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        let palette = ase.palette();
+        assert_eq!(palette.len(), 5);
+        assert_eq!(palette[1].color_name, "red");
+        assert_eq!(palette[2].color_name, "white");
+        assert_eq!(palette[2].green, 255);
+        assert_eq!(palette[3].color_name, "blue");
+        assert_eq!(palette[4].alpha, 0);
+        assert!(palette.get(5).is_none());
+    }
+
+    #[test]
+    fn test_old_palette() {
+        let test_bytes = include_bytes!("../test.ase");
+        let ase = Ase::new(test_bytes).unwrap();
+        let packets = ase.frames[0].chunks.iter().find_map(|chunk| match chunk {
+            Chunk::OldPallette{packets} => Some(packets),
+            _ => None,
+        }).unwrap();
+
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].entries_to_skip, 0);
+        assert_eq!(packets[0].colors.len(), 32);
+        assert_eq!(packets[0].colors[1], [34, 32, 52]);
+    }
+
+    #[test]
+    fn test_only_old_palettes() {
+        let test_bytes = test_util::file(1, 1, 8, &[
+            test_util::frame(100, &[
+                test_util::old_palette(0x0011, &[
+                    (1, vec![[63, 0, 0], [0, 32, 0]]),
+                    (2, vec![[0, 0, 1]]),
+                ]),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        let palette = ase.palette();
+        assert_eq!(palette.len(), 6);
+        assert_eq!(palette[0].alpha, 0);
+        assert_eq!((palette[1].red, palette[1].alpha), (255, 255));
+        assert_eq!(palette[2].green, 130);
+        assert_eq!(palette[5].blue, 4);
+    }
+
+    #[test]
+    fn test_huge_palette() {
+        // claims the biggest palette there is but holds a single entry
+        let mut body = test_util::dword(0x10000);
+        body.extend(test_util::dword(0));
+        body.extend(test_util::dword(0xffff));
+        body.extend(&[0; 8]);
+        body.extend(&[0, 0, 255, 0, 0, 255]);
+        let test_bytes = test_util::file(1, 1, 8, &[
+            test_util::frame(100, &[test_util::chunk(0x2019, &body)]),
+        ]);
+        assert!(matches!(Ase::new(&test_bytes), Err(AseError::Truncated{..})));
+
+        let test_bytes = test_util::file(1, 1, 8, &[
+            test_util::frame(100, &[test_util::palette(u32::MAX, 0, &[([255, 0, 0, 255], None)])]),
+        ]);
+        assert!(matches!(Ase::new(&test_bytes), Err(AseError::UnknownValue{..})));
+
+        // entries past the end of the palette, or a range running backwards
+        for (size, first, last) in [(4, 3_000_000_000, 3_000_000_000), (4, 0, 4), (4, 2, 1)] {
+            let mut body = test_util::dword(size);
+            body.extend(test_util::dword(first));
+            body.extend(test_util::dword(last));
+            body.extend(&[0; 8]);
+            body.extend(&[0, 0, 255, 0, 0, 255]);
+            let test_bytes = test_util::file(1, 1, 8, &[
+                test_util::frame(100, &[test_util::chunk(0x2019, &body)]),
+            ]);
+            assert!(matches!(Ase::new(&test_bytes), Err(AseError::Invalid{offset: 154, ..})), "{}..{}", first, last);
+        }
+    }
+
+    #[test]
+    fn test_new_palette_wins_over_old() {
+        let test_bytes = test_util::file(1, 1, 8, &[
+            test_util::frame(100, &[
+                test_util::old_palette(0x0004, &[(0, vec![[1, 2, 3], [4, 5, 6], [7, 8, 9]])]),
+                test_util::palette(2, 0, &[
+                    ([10, 20, 30, 255], None),
+                    ([40, 50, 60, 255], None),
+                ]),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        assert_eq!(ase.palette().len(), 2);
+        assert_eq!(ase.palette()[0].red, 10);
+    }
+
+    #[test]
+    fn test_tags() {
+        let test_bytes = test_util::file(1, 1, 32, &[
+            test_util::frame(100, &[
+                test_util::tags(&[
+                    (0, 3, 0, 0, [255, 0, 0], "idle"),
+                    (4, 7, 2, 3, [0, 0, 255], "run"),
+                ]),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        assert_eq!(ase.tags().len(), 2);
+
+        let run = ase.tag("run").unwrap();
+        assert_eq!(run.frames(), 4..=7);
+        assert_eq!(run.direction, LoopDirection::PingPong);
+        assert_eq!(run.repeat, 3);
+        assert_eq!(run.color, [0, 0, 255]);
+        assert_eq!(ase.tag("idle").unwrap().direction, LoopDirection::Forward);
+        assert!(ase.tag("jump").is_none());
+    }
+
+    #[test]
+    fn test_slices() {
+        let test_bytes = test_util::file(16, 16, 32, &[
+            test_util::frame(100, &[
+                test_util::slice("hitbox", 0, &[
+                    vec![0, 1, 2, 8, 8],
+                    vec![3, -1, 2, 10, 8],
+                ]),
+                test_util::slice("button", 3, &[
+                    vec![0, 0, 0, 16, 16, 4, 4, 8, 8, 8, 15],
+                ]),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        assert_eq!(ase.slices().len(), 2);
+
+        let hitbox = ase.slice("hitbox").unwrap();
+        assert!(!hitbox.has_nine_patch() && !hitbox.has_pivot());
+        assert_eq!(hitbox.key_for_frame(0).unwrap().x, 1);
+        assert_eq!(hitbox.key_for_frame(2).unwrap().frame_number, 0);
+        let key = hitbox.key_for_frame(5).unwrap();
+        assert_eq!((key.frame_number, key.x, key.width), (3, -1, 10));
+
+        let button = ase.slice("button").unwrap();
+        assert!(button.has_nine_patch() && button.has_pivot());
+        let key = button.key_for_frame(0).unwrap();
+        assert_eq!((key.center_x, key.center_y, key.center_width, key.center_height), (4, 4, 8, 8));
+        assert_eq!((key.pivot_x, key.pivot_y), (8, 15));
+    }
+
+    #[test]
+    fn test_huge_slice() {
+        // claims u32::MAX keys but holds one
+        let mut slice = test_util::slice("hitbox", 0, &[vec![0, 1, 2, 8, 8]]);
+        slice[6..10].copy_from_slice(&test_util::dword(u32::MAX));
+        let test_bytes = test_util::file(16, 16, 32, &[test_util::frame(100, &[slice])]);
+        assert!(matches!(Ase::new(&test_bytes), Err(AseError::Truncated{..})));
+    }
+
+    #[test]
+    fn test_color_profile() {
+        let test_bytes = include_bytes!("../test.ase");
+        let ase = Ase::new(test_bytes).unwrap();
+        assert_eq!(ase.color_profile(), &ColorProfile::Srgb{gamma: None});
+    }
+
+    #[test]
+    fn test_icc_profile() {
+        let icc = test_util::linear_srgb_icc();
+        let gray = [128, 128, 128, 255];
+        let test_bytes = test_util::file(1, 1, 32, &[
+            test_util::frame(100, &[
+                test_util::color_profile(2, 0, 0, &icc),
+                test_util::layer("Background", 1, 0, 0, 0, 255),
+                test_util::compressed_cel(0, 0, 0, 1, 1, &gray),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        match ase.color_profile() {
+            ColorProfile::Icc{data, gamma} => {
+                assert_eq!(data, &icc);
+                assert!(gamma.is_none());
+            },
+            other => panic!("expected icc profile, got {:?}", other),
+        }
+
+        assert_eq!(ase.render()[..3], [128, 128, 128]);
+        let srgb = ase.render_srgb();
+        // linear 0.5 is roughly 188 once sRGB encoded
+        assert!((186..=190).contains(&srgb[0]), "{:?}", srgb);
+        assert_eq!(srgb[0], srgb[1]);
+        assert_eq!(srgb[0], srgb[2]);
+    }
+
+    #[test]
+    fn test_fixed_gamma_profile() {
+        let gray = [128, 128, 128, 255];
+        let test_bytes = test_util::file(1, 1, 32, &[
+            test_util::frame(100, &[
+                test_util::color_profile(1, 1, 0x10000, &[]),
+                test_util::layer("Background", 1, 0, 0, 0, 255),
+                test_util::compressed_cel(0, 0, 0, 1, 1, &gray),
+            ]),
+            test_util::frame(100, &[
+                test_util::raw_cel(0, 0, 0, 1, 1, &[64, 64, 64, 255]),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        assert_eq!(ase.color_profile(), &ColorProfile::Srgb{gamma: Some(1.0)});
+        assert_eq!(ase.render_srgb()[..3], [188, 188, 188]);
+
+        // every frame can be converted
+        let render = |options: RenderOptions| ase.render_with(&options).unwrap().pixel(0, 0).unwrap();
+        assert_eq!(render(RenderOptions::new().frame(1)), [64, 64, 64, 255]);
+        assert_eq!(render(RenderOptions::new().frame(1).srgb(true))[0], 137);
+        assert_eq!(render(RenderOptions::new().srgb(true))[..3], [188, 188, 188]);
+    }
+
+    #[test]
+    fn test_user_data() {
+        let red = [255, 0, 0, 255];
+        let properties = test_util::properties(&[
+            (0, vec![
+                ("damage", 0x0006, (-5i32).to_le_bytes().to_vec()),
+                ("name", 0x000D, test_util::string("sword")),
+                ("origin", 0x000E, [1i32.to_le_bytes(), 2i32.to_le_bytes()].concat()),
+                ("flags", 0x0011, [test_util::dword(2), test_util::word(0x0003), vec![7, 9]].concat()),
+            ]),
+            (3, vec![("enabled", 0x0001, vec![1])]),
+        ]);
+
+        let test_bytes = test_util::file(1, 1, 32, &[
+            test_util::frame(100, &[
+                test_util::palette(1, 0, &[([0, 0, 0, 255], None)]),
+                test_util::user_data(Some("sprite"), None, &[]),
+                test_util::layer("Background", 1, 0, 0, 0, 255),
+                test_util::user_data(Some("layer"), Some([1, 2, 3, 4]), &[]),
+                test_util::compressed_cel(0, 0, 0, 1, 1, &red),
+                test_util::user_data(None, None, &properties),
+                test_util::tags(&[
+                    (0, 0, 0, 0, [0, 0, 0], "idle"),
+                    (0, 0, 0, 0, [0, 0, 0], "attack"),
+                ]),
+                test_util::user_data(Some("first"), None, &[]),
+                test_util::user_data(Some("second"), None, &[]),
+                test_util::slice("hitbox", 0, &[vec![0, 0, 0, 1, 1]]),
+                test_util::user_data(Some("slice"), None, &[]),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        assert_eq!(ase.palette().user_data().unwrap().text.as_deref(), Some("sprite"));
+
+        let layer_data = ase.layers()[0].user_data().unwrap();
+        assert_eq!(layer_data.text.as_deref(), Some("layer"));
+        assert_eq!(layer_data.color, Some([1, 2, 3, 4]));
+
+        let cel_data = ase.cel(0, 0).unwrap().user_data().unwrap();
+        assert!(cel_data.text.is_none());
+        let user = cel_data.user_properties().unwrap();
+        assert_eq!(user["damage"], Value::I32(-5));
+        assert_eq!(user["name"], Value::String(String::from("sword")));
+        assert_eq!(user["origin"], Value::Point{x: 1, y: 2});
+        assert_eq!(user["flags"], Value::Vector(vec![Value::U8(7), Value::U8(9)]));
+        assert_eq!(cel_data.properties[&3]["enabled"], Value::Bool(true));
+
+        assert_eq!(ase.tag("idle").unwrap().user_data().unwrap().text.as_deref(), Some("first"));
+        assert_eq!(ase.tag("attack").unwrap().user_data().unwrap().text.as_deref(), Some("second"));
+        assert_eq!(ase.slice("hitbox").unwrap().user_data().unwrap().text.as_deref(), Some("slice"));
+    }
+
+    #[test]
+    fn test_tilemap() {
+        let empty = [0; 2 * 2 * 4];
+        let red = [255, 0, 0, 255].repeat(4);
+        let tiles = [empty.to_vec(), red.clone()].concat();
+        let tile_ids = [0u32, 1, 1 | 0x2000_0000, 1 | 0x8000_0000]
+            .iter()
+            .flat_map(|id| id.to_le_bytes().to_vec())
+            .collect::<Vec<u8>>();
+
+        let test_bytes = test_util::file(4, 4, 32, &[
+            test_util::frame(100, &[
+                test_util::tileset(0, 2, 2, 2, 1, "terrain", &tiles),
+                test_util::user_data(Some("terrain"), None, &[]),
+                test_util::user_data(None, None, &[]),
+                test_util::user_data(Some("lava"), None, &[]),
+                test_util::tilemap_layer("Map", 0),
+                test_util::tilemap_cel(0, 0, 0, 2, 2, &tile_ids),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        let tileset = &ase.tilesets()[0];
+        assert_eq!((tileset.tile_count, tileset.tile_width, tileset.tile_height), (2, 2, 2));
+        assert_eq!(tileset.base_index, 1);
+        assert_eq!(tileset.name, "terrain");
+        assert!(tileset.external.is_none());
+        assert_eq!(tileset.tile(1).unwrap().len(), 4);
+        assert!(tileset.tile(2).is_none());
+        assert_eq!(tileset.user_data().unwrap().text.as_deref(), Some("terrain"));
+        assert_eq!(tileset.tile_user_data(1).unwrap().text.as_deref(), Some("lava"));
+
+        assert_eq!(ase.layers()[0].tileset_index(), Some(0));
+        let tilemap = match ase.cel(0, 0) {
+            Some(Cel::Tilemap(c)) => c,
+            other => panic!("expected tilemap cel, got {:?}", other),
+        };
+
+        assert_eq!(tilemap.tiles().len(), 4);
+        assert_eq!(tilemap.tile(0, 0).unwrap().id, 0);
+        assert_eq!(tilemap.tile(1, 0).unwrap(), &Tile{id: 1, x_flip: false, y_flip: false, diagonal_flip: false});
+        assert!(tilemap.tile(0, 1).unwrap().x_flip);
+        assert!(tilemap.tile(1, 1).unwrap().diagonal_flip);
+        assert!(tilemap.tile(2, 0).is_none());
+    }
+
+    #[test]
+    fn test_huge_tileset() {
+        // the claimed size of the tiles doesn't fit in memory at all
+        let test_bytes = test_util::file(4, 4, 32, &[
+            test_util::frame(100, &[test_util::tileset(0, u32::MAX, 65535, 65535, 1, "terrain", &[])]),
+        ]);
+        assert!(matches!(Ase::new(&test_bytes), Err(AseError::UnknownValue{..})));
+
+        let test_bytes = test_util::file(4, 4, 32, &[
+            test_util::frame(100, &[test_util::tileset(0, u32::MAX, 0, 0, 1, "terrain", &[])]),
+        ]);
+        assert!(matches!(Ase::new(&test_bytes), Err(AseError::UnknownValue{..})));
+
+        let test_bytes = test_util::file(4, 4, 32, &[
+            test_util::frame(100, &[test_util::tileset(0, 1000, 16, 16, 1, "terrain", &[])]),
+        ]);
+        assert!(matches!(Ase::new(&test_bytes), Err(AseError::Decompression{..})));
+    }
+
+    #[test]
+    fn test_external_files() {
+        let red = [255, 0, 0, 255].repeat(4);
+        let library = test_util::file(2, 2, 32, &[
+            test_util::frame(100, &[
+                test_util::tileset(0, 1, 2, 2, 1, "unused", &[0; 16]),
+                test_util::tileset(7, 2, 2, 2, 1, "shared", &[vec![0; 16], red.clone()].concat()),
+            ]),
+        ]);
+        let colors = test_util::file(1, 1, 32, &[
+            test_util::frame(100, &[test_util::palette(2, 0, &[([1, 2, 3, 255], None), ([4, 5, 6, 255], None)])]),
+        ]);
+
+        let test_bytes = test_util::file(4, 4, 32, &[
+            test_util::frame(100, &[
+                test_util::external_files(&[(1, 0, "colors.aseprite"), (2, 1, "library.aseprite"), (3, 2, "ext.props")]),
+                test_util::external_tileset(0, 2, 2, "terrain", 2, 7),
+                test_util::external_tileset(1, 2, 2, "terrain again", 2, 7),
+            ]),
+        ]);
+
+        let mut ase = Ase::new(&test_bytes).unwrap();
+        let files = ase.external_files();
+        assert_eq!(files.len(), 3);
+        assert_eq!((files[0].id, files[0].file_type), (1, ExternalFileType::Palette));
+        assert_eq!(files[1].name, "library.aseprite");
+        assert_eq!(files[2].file_type, ExternalFileType::PropertiesExtension);
+        let reference = ase.tilesets()[0].external.unwrap();
+        assert_eq!((reference.file_id, reference.tileset_id), (2, 7));
+        assert!(!ase.tilesets()[0].has_embedded_tiles());
+
+        let mut requested = Vec::new();
+        ase.resolve_external_files(&mut |file: &ExternalFile| {
+            requested.push(file.name.clone());
+            match file.name.as_str() {
+                "library.aseprite" => Ok(library.clone()),
+                "colors.aseprite" => Ok(colors.clone()),
+                _ => Err(io::Error::from(io::ErrorKind::NotFound)),
+            }
+        }).unwrap();
+
+        // each file is only loaded once and extensions are never requested
+        assert_eq!(requested, ["library.aseprite", "colors.aseprite"]);
+        for tileset in ase.tilesets() {
+            assert_eq!(tileset.tile_count, 2);
+            assert_eq!(tileset.tile(1).unwrap().len(), 4);
+        }
+        assert_eq!(ase.palette().len(), 2);
+        assert_eq!(ase.palette()[1].blue, 6);
+
+        // claims u32::MAX entries but holds one
+        let mut files = test_util::external_files(&[(1, 0, "colors.aseprite")]);
+        files[6..10].copy_from_slice(&test_util::dword(u32::MAX));
+        let test_bytes = test_util::file(4, 4, 32, &[test_util::frame(100, &[files])]);
+        assert!(matches!(Ase::new(&test_bytes), Err(AseError::Truncated{..})));
+    }
+
+    #[test]
+    fn test_external_files_from_disk() {
+        let dir = std::env::temp_dir().join(format!("ase-external-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let library = test_util::file(2, 2, 32, &[
+            test_util::frame(100, &[test_util::tileset(0, 1, 2, 2, 1, "shared", &[9; 16])]),
+        ]);
+        fs::write(dir.join("library.aseprite"), library).unwrap();
+
+        let test_bytes = test_util::file(4, 4, 32, &[
+            test_util::frame(100, &[
+                test_util::external_files(&[(1, 1, "library.aseprite"), (2, 1, "missing.aseprite")]),
+                test_util::external_tileset(0, 2, 2, "terrain", 1, 0),
+            ]),
+        ]);
+        let mut ase = Ase::new(&test_bytes).unwrap();
+        ase.resolve_external_files(&mut FileSystemResolver::new(dir.join("sprite.aseprite"))).unwrap();
+        assert!(ase.tilesets()[0].has_embedded_tiles());
+
+        let test_bytes = test_util::file(4, 4, 32, &[
+            test_util::frame(100, &[
+                test_util::external_files(&[(2, 1, "missing.aseprite")]),
+                test_util::external_tileset(0, 2, 2, "terrain", 2, 0),
+            ]),
+        ]);
+        let mut ase = Ase::new(&test_bytes).unwrap();
+        let err = ase.resolve_external_files(&mut FileSystemResolver::from_dir(&dir)).unwrap_err();
+        match err {
+            AseError::ExternalFile{ref name, ..} => assert_eq!(name, "missing.aseprite"),
+            other => panic!("expected external file error, got {:?}", other),
+        }
+        assert!(err.source().is_some());
+
+        // names can't reach outside the sprite's directory
+        let outside = dir.join("..").join("outside.aseprite");
+        for name in ["../library.aseprite", "sub/../../library.aseprite", outside.to_str().unwrap()] {
+            let test_bytes = test_util::file(4, 4, 32, &[
+                test_util::frame(100, &[
+                    test_util::external_files(&[(1, 1, name)]),
+                    test_util::external_tileset(0, 2, 2, "terrain", 1, 0),
+                ]),
+            ]);
+            let mut ase = Ase::new(&test_bytes).unwrap();
+            let err = ase.resolve_external_files(&mut FileSystemResolver::from_dir(dir.join("sub"))).unwrap_err();
+            let source = err.source().and_then(|source| source.downcast_ref::<io::Error>()).unwrap();
+            assert_eq!(source.kind(), io::ErrorKind::PermissionDenied);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_blend_modes() {
+        fn rgba(r: u8, g: u8, b: u8, a: u8) -> Rgba8 {
+            Rgba8{r, g, b, a}
+        }
+
+        // expected values follow Aseprite's blend functions for an opaque backdrop and source
+        let backdrop = rgba(200, 100, 50, 255);
+        let src = rgba(100, 150, 220, 255);
+        let expected = [
+            (BlendMode::Normal, [100, 150, 220]),
+            (BlendMode::Multiply, [78, 59, 43]),
+            (BlendMode::Screen, [222, 191, 227]),
+            (BlendMode::Overlay, [188, 118, 86]),
+            (BlendMode::Darken, [100, 100, 50]),
+            (BlendMode::Lighten, [200, 150, 220]),
+            (BlendMode::ColorDodge, [255, 243, 255]),
+            (BlendMode::ColorBurn, [115, 0, 17]),
+            (BlendMode::HardLight, [157, 127, 199]),
+            (BlendMode::SoftLight, [191, 111, 96]),
+            (BlendMode::Difference, [100, 50, 170]),
+            (BlendMode::Exclusion, [144, 132, 184]),
+            (BlendMode::Hue, [71, 133, 221]),
+            (BlendMode::Saturation, [184, 104, 64]),
+            (BlendMode::Color, [81, 131, 201]),
+            (BlendMode::Luminosity, [218, 118, 68]),
+            (BlendMode::Addition, [255, 250, 255]),
+            (BlendMode::Subtract, [100, 0, 0]),
+            (BlendMode::Divide, [255, 170, 58]),
+        ];
+
+        for (mode, color) in expected.iter() {
+            let result = blend::blend(*mode, &backdrop, &src, 255);
+            assert_eq!([result.r, result.g, result.b, result.a], [color[0], color[1], color[2], 255], "{:?}", mode);
+        }
+
+        // opacity mixes the blended color with the backdrop
+        let result = blend::blend(BlendMode::Normal, &rgba(0, 0, 0, 255), &rgba(255, 255, 255, 255), 128);
+        assert_eq!([result.r, result.g, result.b, result.a], [128, 128, 128, 255]);
+
+        // nothing to blend with on a transparent backdrop, so the source shows through as is
+        let result = blend::blend(BlendMode::Multiply, &rgba(0, 0, 0, 0), &rgba(10, 20, 30, 200), 255);
+        assert_eq!([result.r, result.g, result.b, result.a], [10, 20, 30, 200]);
+
+        // a half transparent backdrop fades from the normal composite towards the blended color
+        // twice, first by the backdrop's alpha and then by both alphas together
+        let result = blend::blend(BlendMode::Multiply, &rgba(200, 100, 50, 128), &src, 255);
+        assert_eq!([result.r, result.g, result.b, result.a], [83, 81, 87, 255]);
+
+        // and a transparent source leaves the backdrop alone
+        let result = blend::blend(BlendMode::Screen, &backdrop, &rgba(255, 255, 255, 0), 255);
+        assert_eq!([result.r, result.g, result.b, result.a], [200, 100, 50, 255]);
+    }
+
+    #[test]
+    #[ignore = "needs the Aseprite exports described in fixtures/blend/README.md"]
+    fn test_blend_mode_references() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/blend");
+        let mut sprites = 0;
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("aseprite") {
+                continue;
+            }
+
+            sprites += 1;
+            let image = Ase::from_path(&path).unwrap().render_frame(0).unwrap();
+            let reference = path.with_extension("png");
+            assert!(reference.exists(), "{} has no reference", path.display());
+            let expected = image::open(&reference).unwrap().to_rgba();
+            assert_eq!((image.width(), image.height()), (expected.width() as usize, expected.height() as usize));
+            let expected = expected.into_raw();
+            let pixels = image.data().chunks(4).zip(expected.chunks(4)).enumerate();
+            for (idx, (actual, expected)) in pixels {
+                // the color of a fully transparent pixel doesn't matter
+                if actual[3] == 0 && expected[3] == 0 {
+                    continue;
+                }
+
+                assert_eq!(actual, expected, "{} at {}", path.display(), idx);
+            }
+        }
+
+        assert_eq!(sprites, 19);
+    }
+
+    #[test]
+    fn test_render_blend_mode() {
+        let test_bytes = test_util::file(1, 1, 32, &[
+            test_util::frame(100, &[
+                test_util::layer("Background", 1, 0, 0, 0, 255),
+                test_util::layer("Shadow", 1, 0, 0, 1, 128),
+                test_util::raw_cel(0, 0, 0, 1, 1, &[200, 100, 50, 255]),
+                test_util::raw_cel(1, 0, 0, 1, 1, &[100, 150, 220, 255]),
+            ]),
+        ]);
+
+        // multiply gives (78, 59, 43), which the half opaque layer mixes halfway into the backdrop
+        let ase = Ase::new(&test_bytes).unwrap();
+        assert!(ase.header.has_layer_opacity());
+        assert_eq!(ase.render(), [139, 80, 47, 255]);
+
+        // without the header flag layer opacity is ignored
+        let mut test_bytes = test_bytes;
+        test_bytes[14..18].copy_from_slice(&test_util::dword(0));
+        let ase = Ase::new(&test_bytes).unwrap();
+        assert!(!ase.header.has_layer_opacity());
+        assert_eq!(ase.render(), [78, 59, 43, 255]);
+    }
+
+    #[test]
+    fn test_render_groups() {
+        let test_bytes = test_util::file(2, 1, 32, &[
+            test_util::frame(100, &[
+                test_util::layer("Background", 1, 0, 0, 0, 255),
+                test_util::layer("Shadow", 1, 1, 0, 1, 128),
+                test_util::layer("Inside", 1, 0, 1, 0, 255),
+                test_util::layer("Plain", 1, 1, 0, 0, 255),
+                test_util::layer("Bottom", 1, 0, 1, 0, 255),
+                test_util::layer("Top", 1, 0, 1, 0, 128),
+                test_util::raw_cel(0, 0, 0, 2, 1, &[200, 100, 50, 255, 200, 100, 50, 255]),
+                test_util::raw_cel(2, 0, 0, 1, 1, &[100, 150, 220, 255]),
+                test_util::raw_cel(4, 1, 0, 1, 1, &[0, 0, 255, 255]),
+                test_util::raw_cel(5, 1, 0, 1, 1, &[255, 255, 255, 255]),
+            ]),
+        ]);
+
+        // the group multiplies what's inside it onto the backdrop at half opacity, the same as a
+        // single layer would
+        let ase = Ase::new(&test_bytes).unwrap();
+        let image = ase.render_frame(0).unwrap();
+        assert_eq!(image.pixel(0, 0), Some([139, 80, 47, 255]));
+        // a normal group at full opacity changes nothing
+        assert_eq!(image.pixel(1, 0), Some([128, 128, 255, 255]));
+
+        // older files ignore how groups are set up
+        let mut test_bytes = test_bytes;
+        test_bytes[14..18].copy_from_slice(&test_util::dword(1));
+        let ase = Ase::new(&test_bytes).unwrap();
+        let image = ase.render_frame(0).unwrap();
+        assert_eq!(image.pixel(0, 0), Some([100, 150, 220, 255]));
+        assert_eq!(image.pixel(1, 0), Some([128, 128, 255, 255]));
+    }
+
+    #[test]
+    fn test_render_z_index() {
+        let red = [255, 0, 0, 255];
+        let green = [0, 255, 0, 255];
+        let blue = [0, 0, 255, 255];
+        let test_bytes = test_util::file(1, 1, 32, &[
+            test_util::frame(100, &[
+                test_util::layer("Bottom", 1, 0, 0, 0, 255),
+                test_util::layer("Middle", 1, 0, 0, 0, 255),
+                test_util::layer("Top", 1, 0, 0, 0, 255),
+                test_util::z_indexed(test_util::raw_cel(0, 0, 0, 1, 1, &red), 2),
+                test_util::raw_cel(1, 0, 0, 1, 1, &green),
+                test_util::raw_cel(2, 0, 0, 1, 1, &blue),
+            ]),
+            test_util::frame(100, &[
+                test_util::raw_cel(0, 0, 0, 1, 1, &red),
+                test_util::raw_cel(1, 0, 0, 1, 1, &green),
+                test_util::z_indexed(test_util::raw_cel(2, 0, 0, 1, 1, &blue), -1),
+            ]),
+            test_util::frame(100, &[
+                test_util::z_indexed(test_util::raw_cel(0, 0, 0, 1, 1, &red), 1),
+                test_util::raw_cel(1, 0, 0, 1, 1, &green),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        assert_eq!(ase.cel(0, 0).unwrap().z_index(), 2);
+        // moved up past the top layer, and landing level with it goes on top
+        assert_eq!(ase.render_frame(0).unwrap().pixel(0, 0), Some(red));
+        // moved down below the middle layer
+        assert_eq!(ase.render_frame(1).unwrap().pixel(0, 0), Some(green));
+        assert_eq!(ase.render_frame(2).unwrap().pixel(0, 0), Some(red));
+    }
+
+    #[test]
+    fn test_render_tilemap() {
+        let (a, b, c, d) = ([255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [255, 255, 255, 255]);
+        let tiles = [vec![0; 16], [a, b, c, d].concat()].concat();
+        let tile_ids = [1u32, 1 | 0x2000_0000, 1 | 0x4000_0000, 1 | 0x8000_0000]
+            .iter()
+            .flat_map(|id| id.to_le_bytes().to_vec())
+            .collect::<Vec<u8>>();
+
+        let test_bytes = test_util::file(4, 4, 32, &[
+            test_util::frame(100, &[
+                test_util::tileset(0, 2, 2, 2, 1, "terrain", &tiles),
+                test_util::tilemap_layer("Map", 0),
+                test_util::tilemap_cel(0, 0, 0, 2, 2, &tile_ids),
+            ]),
+            test_util::frame(100, &[
+                test_util::linked_cel(0, 0, 0, 0),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        let (image, diagnostics) = ase.render_with_diagnostics(&RenderOptions::new()).unwrap();
+        assert!(diagnostics.is_empty());
+        let rows: Vec<Vec<[u8; 4]>> = (0..4)
+            .map(|y| (0..4).map(|x| image.pixel(x, y).unwrap()).collect())
+            .collect();
+        // as is, flipped along x, flipped along y, and flipped diagonally
+        assert_eq!(rows[0], [a, b, b, a]);
+        assert_eq!(rows[1], [c, d, d, c]);
+        assert_eq!(rows[2], [c, d, a, c]);
+        assert_eq!(rows[3], [a, b, b, d]);
+        assert_eq!(ase.render_frame(1).unwrap(), image);
+
+        // without its tileset the tilemap has nothing to draw
+        let test_bytes = test_util::file(4, 4, 32, &[
+            test_util::frame(100, &[
+                test_util::tilemap_layer("Map", 3),
+                test_util::tilemap_cel(0, 0, 0, 2, 2, &tile_ids),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        let (image, diagnostics) = ase.render_with_diagnostics(&RenderOptions::new()).unwrap();
+        assert!(image.data().iter().all(|&byte| byte == 0));
+        assert_eq!(diagnostics.warnings(), [Warning::MissingTiles{frame: 0, layer: 0}]);
+    }
+
+    #[test]
+    fn test_render_frames() {
+        let red = [255, 0, 0, 255];
+        let green = [0, 255, 0, 255];
+        let blue = [0, 0, 255, 255];
+        let white = [255; 4];
+        let test_bytes = test_util::file(2, 1, 32, &[
+            test_util::frame(100, &[
+                test_util::layer("Background", 1, 0, 0, 0, 255),
+                test_util::layer("Hidden", 0, 1, 0, 0, 255),
+                test_util::layer("Inside", 1, 0, 1, 0, 255),
+                test_util::layer("Top", 1, 0, 0, 0, 255),
+                test_util::raw_cel(0, 0, 0, 1, 1, &red),
+                test_util::raw_cel(2, 0, 0, 1, 1, &blue),
+                test_util::raw_cel(3, 1, 0, 1, 1, &green),
+            ]),
+            test_util::frame(250, &[
+                test_util::linked_cel(0, 0, 0, 0),
+                test_util::raw_cel(3, 0, 0, 1, 1, &white),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        let first = ase.render_frame(0).unwrap();
+        assert_eq!((first.width(), first.height()), (2, 1));
+        assert_eq!(first.pixel(0, 0), Some(red));
+        assert_eq!(first.pixel(1, 0), Some(green));
+        assert_eq!(first.pixel(2, 0), None);
+        assert_eq!(ase.render(), first.data());
+
+        let frames: Vec<RenderedFrame> = ase.render_frames().collect();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].image, first);
+        assert_eq!(frames[0].duration, 100);
+        assert_eq!(frames[1].duration, 250);
+        assert_eq!(frames[1].image.pixel(0, 0), Some(white));
+        assert_eq!(frames[1].image.pixel(1, 0), Some([0; 4]));
+        assert!(ase.render_frame(2).is_none());
+    }
+
+    #[test]
+    fn test_cel_placement() {
+        let red = [255, 0, 0, 255];
+        let green = [0, 255, 0, 255];
+        let blue = [0, 0, 255, 255];
+        let test_bytes = test_util::file(3, 3, 32, &[
+            test_util::frame(100, &[
+                test_util::layer("Corners", 1, 0, 0, 0, 255),
+                test_util::layer("Middle", 1, 0, 0, 0, 255),
+                test_util::layer("Gone", 1, 0, 0, 0, 255),
+                test_util::raw_cel(0, -1, -1, 2, 2, &red.repeat(4)),
+                test_util::raw_cel(1, 1, 1, 3, 1, &blue.repeat(3)),
+                test_util::raw_cel(2, -5, 10, 2, 2, &green.repeat(4)),
+            ]),
+            test_util::frame(100, &[
+                test_util::compressed_cel(0, 2, 2, 2, 2, &green.repeat(4)),
+                test_util::raw_cel(1, 0, 2, 1, 3, &blue.repeat(3)),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        let image = ase.render_frame(0).unwrap();
+        let clear = [0; 4];
+        let rows: Vec<Vec<[u8; 4]>> = (0..3)
+            .map(|y| (0..3).map(|x| image.pixel(x, y).unwrap()).collect())
+            .collect();
+        assert_eq!(rows, [
+            vec![red, clear, clear],
+            vec![clear, blue, blue],
+            vec![clear, clear, clear],
+        ]);
+
+        let image = ase.render_frame(1).unwrap();
+        assert_eq!(image.pixel(2, 2), Some(green));
+        assert_eq!(image.pixel(0, 2), Some(blue));
+        assert_eq!(image.data().chunks(4).filter(|pixel| pixel[3] != 0).count(), 2);
+    }
+
+    #[test]
+    fn test_render_indexed() {
+        let black = [0, 0, 0, 255];
+        let red = [255, 0, 0, 255];
+        let green = [0, 255, 0, 255];
+        let test_bytes = test_util::file(3, 1, 8, &[
+            test_util::frame(100, &[
+                test_util::palette(3, 0, &[(black, None), (red, None), (green, None)]),
+                test_util::layer("Background", 1 | 8, 0, 0, 0, 255),
+                test_util::layer("Top", 1, 0, 0, 0, 255),
+                test_util::raw_cel(0, 0, 0, 3, 1, &[0, 1, 1]),
+                test_util::raw_cel(1, 0, 0, 3, 1, &[0, 2, 5]),
+            ]),
+        ]);
+
+        // the transparent index only applies outside the background layer
+        let ase = Ase::new(&test_bytes).unwrap();
+        assert_eq!(ase.render(), [black, green, red].concat());
+
+        let mut test_bytes = test_util::file(3, 1, 8, &[
+            test_util::frame(100, &[
+                test_util::palette(3, 0, &[(black, None), (red, None), (green, None)]),
+                test_util::layer("Layer", 1, 0, 0, 0, 255),
+                test_util::raw_cel(0, 0, 0, 3, 1, &[0, 1, 2]),
+            ]),
+        ]);
+        test_bytes[28] = 1;
+        let ase = Ase::new(&test_bytes).unwrap();
+        assert_eq!(ase.header.transparent_index(), 1);
+        let image = ase.render_frame(0).unwrap();
+        assert_eq!(image.pixel(0, 0), Some(black));
+        assert_eq!(image.pixel(1, 0).unwrap()[3], 0);
+        assert_eq!(image.pixel(2, 0), Some(green));
+    }
+
+    #[test]
+    fn test_render_grayscale() {
+        let test_bytes = test_util::file(2, 1, 16, &[
+            test_util::frame(100, &[
+                test_util::layer("Background", 1 | 8, 0, 0, 0, 255),
+                test_util::layer("Ink", 1, 0, 0, 0, 255),
+                test_util::raw_cel(0, 0, 0, 2, 1, &[255, 255, 200, 255]),
+                test_util::raw_cel(1, 0, 0, 2, 1, &[128, 255, 0, 0]),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        assert_eq!(ase.render(), [128, 128, 128, 255, 200, 200, 200, 255]);
+    }
+
+    #[test]
+    fn test_render_native() {
+        let black = [0, 0, 0, 255];
+        let red = [255, 0, 0, 255];
+        let green = [0, 255, 0, 255];
+        let test_bytes = test_util::file(3, 1, 8, &[
+            test_util::frame(100, &[
+                test_util::palette(3, 0, &[(black, None), (red, None), (green, None)]),
+                test_util::layer("Background", 1 | 8, 0, 0, 0, 255),
+                test_util::layer("Top", 1, 0, 0, 0, 255),
+                test_util::raw_cel(0, 0, 0, 3, 1, &[0, 1, 1]),
+                test_util::raw_cel(1, 0, 0, 3, 1, &[0, 2, 5]),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        let image = ase.render_frame_indexed(0).unwrap();
+        assert_eq!(image.indices(), [0, 2, 5]);
+        assert_eq!(image.index(1, 0), Some(2));
+        assert_eq!(image.palette(), [black, red, green]);
+        assert_eq!(image.transparent_index(), 0);
+        assert!(ase.render_frame_grayscale(0).is_none());
+        assert!(ase.render_frame_indexed(1).is_none());
+
+        let test_bytes = test_util::file(2, 1, 16, &[
+            test_util::frame(100, &[
+                test_util::layer("Background", 1 | 8, 0, 0, 0, 255),
+                test_util::layer("Ink", 1, 0, 0, 0, 255),
+                test_util::raw_cel(0, 0, 0, 2, 1, &[255, 255, 200, 255]),
+                test_util::raw_cel(1, 0, 0, 2, 1, &[128, 255, 0, 0]),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        let image = ase.render_frame_grayscale(0).unwrap();
+        assert_eq!(image.data(), [128, 255, 200, 255]);
+        assert_eq!(image.pixel(1, 0), Some([200, 255]));
+        assert!(ase.render_frame_indexed(0).is_none());
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_paletted_png() {
+        let test_bytes = test_util::file(3, 1, 8, &[
+            test_util::frame(100, &[
+                test_util::palette(2, 0, &[([0, 0, 0, 255], None), ([255, 0, 0, 128], None)]),
+                test_util::layer("Layer", 1, 0, 0, 0, 255),
+                test_util::raw_cel(0, 0, 0, 3, 1, &[1, 0, 3]),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        let mut bytes = Vec::new();
+        ase.render_frame_indexed(0).unwrap().write_png(&mut bytes).unwrap();
+
+        let mut reader = png::Decoder::new(&bytes[..]).read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        let info = reader.info();
+        assert_eq!(info.color_type, png::ColorType::Indexed);
+        assert_eq!((info.width, info.height), (3, 1));
+        // padded out to cover index 3
+        assert_eq!(info.palette.as_deref().unwrap(), [0, 0, 0, 255, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(info.trns.as_deref().unwrap(), [0, 128, 0, 0]);
+        assert_eq!(data, [1, 0, 3]);
+    }
+
+    #[test]
+    fn test_render_options() {
+        let pixel = |color: u8| [color, color, color, 255];
+        let test_bytes = test_util::file(1, 1, 32, &[
+            test_util::frame(100, &[
+                test_util::layer("Background", 1 | 8, 0, 0, 0, 255),
+                test_util::layer("body", 1, 1, 0, 0, 255),
+                test_util::layer("shadow", 1, 0, 1, 0, 255),
+                test_util::layer("arm", 0, 0, 1, 0, 255),
+                test_util::layer("guides", 1 | 64, 0, 0, 0, 255),
+                test_util::layer("stash", 0, 1, 0, 0, 255),
+                test_util::layer("hat", 1, 0, 1, 0, 255),
+                test_util::raw_cel(0, 0, 0, 1, 1, &pixel(10)),
+                test_util::raw_cel(2, 0, 0, 1, 1, &pixel(20)),
+                test_util::raw_cel(3, 0, 0, 1, 1, &pixel(30)),
+                test_util::raw_cel(4, 0, 0, 1, 1, &pixel(40)),
+                test_util::raw_cel(6, 0, 0, 1, 1, &pixel(60)),
+            ]),
+            test_util::frame(100, &[
+                test_util::raw_cel(0, 0, 0, 1, 1, &pixel(50)),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        let render = |options: RenderOptions| ase.render_with(&options).unwrap().pixel(0, 0).unwrap();
+        assert_eq!(render(RenderOptions::new()), pixel(40));
+        assert_eq!(render(RenderOptions::new().skip_reference_layers(true)), pixel(20));
+        assert_eq!(render(RenderOptions::new().exclude("guides").show("arm")), pixel(30));
+        // showing a layer shows the hidden groups it's in, unless they're hidden explicitly
+        assert_eq!(render(RenderOptions::new().show("hat")), pixel(60));
+        assert_eq!(render(RenderOptions::new().show("stash")), pixel(60));
+        assert_eq!(render(RenderOptions::new().show("hat").hide("stash")), pixel(40));
+        assert_eq!(render(RenderOptions::new().include("body/shadow")), pixel(20));
+        assert_eq!(render(RenderOptions::new().include("body").exclude("shadow")), [0; 4]);
+        assert_eq!(render(RenderOptions::new().include("Background").include("guides").hide("guides")), pixel(10));
+        assert_eq!(render(RenderOptions::new().include("shadow").hide("body")), [0; 4]);
+        assert_eq!(render(RenderOptions::new().frame(1)), pixel(50));
+        assert_eq!(render(RenderOptions::new().frame(1).include_background(false)), [0; 4]);
+        assert_eq!(
+            render(RenderOptions::new().include("nothing").background_color([1, 2, 3, 255])),
+            [1, 2, 3, 255]
+        );
+        assert!(ase.render_with(&RenderOptions::new().frame(2)).is_none());
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let red = [255, 0, 0, 255];
+        let test_bytes = test_util::file(2, 2, 32, &[
+            test_util::frame(100, &[
+                test_util::old_palette(0x0004, &[(0, vec![[1, 2, 3]])]),
+                test_util::chunk(0x2017, &[]),
+                test_util::layer("Layer", 1, 0, 0, 0, 255),
+                test_util::raw_cel(0, 1, 0, 2, 1, &red.repeat(2)),
+            ]),
+            test_util::frame(100, &[
+                test_util::raw_cel(0, 0, 0, 1, 1, &red),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        assert_eq!(ase.diagnostics().warnings(), [
+            Warning::DeprecatedChunk{offset: 157, chunk_type: 0x2017},
+            Warning::OldPalette,
+        ]);
+        // an old palette written next to a new one is just there for older readers
+        assert!(Ase::new(include_bytes!("../test.ase")).unwrap().diagnostics().is_empty());
+
+        let (image, diagnostics) = ase.render_with_diagnostics(&RenderOptions::new()).unwrap();
+        assert_eq!(image.pixel(1, 0), Some(red));
+        assert_eq!(diagnostics.warnings(), [Warning::ClippedCel{frame: 0, layer: 0}]);
+        let (_, diagnostics) = ase.render_with_diagnostics(&RenderOptions::new().frame(1)).unwrap();
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_render_image() {
+        let test_bytes = include_bytes!("../test.ase");
+        let ase = Ase::new(test_bytes).unwrap();
         let image_data = ase.render();
         let file = fs::File::create("output.png").unwrap();
         let encoder = PNGEncoder::new(file);
@@ -764,4 +2617,307 @@ mod tests {
         let height = ase.header.height as u32;
         encoder.encode(&image_data, width, height, image::ColorType::RGBA(8)).unwrap();
     }
+
+    #[test]
+    fn test_from_reader() {
+        let test_bytes = include_bytes!("../test.ase");
+        let ase = Ase::new(test_bytes).unwrap();
+        let read = Ase::from_reader(&test_bytes[..]).unwrap();
+        assert_eq!(read.frames.len(), ase.frames.len());
+        assert_eq!(read.frames[0].chunks.len(), ase.frames[0].chunks.len());
+        assert_eq!(read.layers().len(), ase.layers().len());
+        assert_eq!(read.palette().len(), ase.palette().len());
+        assert_eq!(read.render(), ase.render());
+
+        // the reader never holds the whole frame, so it notices the end of the data at the chunk
+        // that runs past it
+        match Ase::from_reader(&test_bytes[..500]) {
+            Err(AseError::Truncated{offset, ..}) => assert_eq!(offset, 496),
+            other => panic!("expected truncated data, got {:?}", other),
+        }
+
+        // a frame padded past the end of the data is truncated too
+        let mut cut = test_util::file(1, 1, 32, &[test_util::frame(100, &[])]);
+        cut[128..132].copy_from_slice(&test_util::dword(26));
+        match Ase::from_reader(&cut[..]) {
+            Err(AseError::Truncated{offset, needed, available}) => assert_eq!((offset, needed, available), (144, 10, 0)),
+            other => panic!("expected truncated data, got {:?}", other),
+        }
+
+        let mut test_bytes = test_bytes.to_vec();
+        test_bytes[148] = 0x34;
+        test_bytes[149] = 0x12;
+        let read = Ase::from_reader(&test_bytes[..]).unwrap();
+        // warnings point at the same offsets as when parsing from memory
+        assert_eq!(read.diagnostics().warnings(), [Warning::UnknownChunk{offset: 144, chunk_type: 0x1234}]);
+
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("broken"))
+            }
+        }
+
+        assert!(matches!(Ase::from_reader(Broken), Err(AseError::Io{offset: 0, ..})));
+    }
+
+    #[test]
+    fn test_from_path() {
+        let path = std::env::temp_dir().join(format!("ase-from-path-{}.ase", std::process::id()));
+        std::fs::write(&path, include_bytes!("../test.ase")).unwrap();
+        let ase = Ase::from_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(ase.unwrap().render(), Ase::new(include_bytes!("../test.ase")).unwrap().render());
+
+        assert!(matches!(Ase::from_path(path), Err(AseError::Io{offset: 0, ..})));
+    }
+
+    #[test]
+    fn test_frame_reader() {
+        let test_bytes = include_bytes!("../test.ase");
+        let ase = Ase::new(test_bytes).unwrap();
+        let mut reader = FrameReader::new(&test_bytes[..]).unwrap();
+        assert_eq!(reader.header().frame_count() as usize, ase.frames.len());
+        assert_eq!((reader.header().width(), reader.header().height()), (ase.header.width, ase.header.height));
+
+        let mut count = 0;
+        for (frame_index, frame) in reader.by_ref().enumerate() {
+            let frame = frame.unwrap();
+            assert_eq!(frame.frame_duration, ase.frames[frame_index].frame_duration);
+            for cel in frame.cels() {
+                let layer_index = cel.layer_index() as usize;
+                match cel {
+                    Cel::Compressed(compressed) => {
+                        let decoded = compressed.decode().unwrap();
+                        let parsed = ase.resolve_cel(frame_index, layer_index).unwrap();
+                        assert_eq!((decoded.width(), decoded.height()), (parsed.width(), parsed.height()));
+                        assert_eq!(decoded.pixels(), parsed.pixels());
+                    },
+                    Cel::Linked(_) => (),
+                    _ => panic!("expected cels to stay compressed"),
+                }
+            }
+
+            count += 1;
+        }
+
+        assert_eq!(count, ase.frames.len());
+        assert!(reader.next().is_none());
+
+        // iteration stops at the first error
+        let mut reader = FrameReader::new(&test_bytes[..500]).unwrap();
+        assert!(matches!(reader.next(), Some(Err(AseError::Truncated{..}))));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_pixels() {
+        let test_bytes = test_util::file(2, 2, 16, &[
+            test_util::frame(100, &[
+                test_util::layer("Layer", 1, 0, 0, 0, 255),
+                test_util::raw_cel(0, 0, 0, 2, 2, &[10, 255, 20, 255, 30, 128, 40, 0]),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        assert_eq!(ase.header.color_depth(), ColorDepth::GrayScale);
+        let pixels = ase.resolve_cel(0, 0).unwrap().pixels();
+        assert_eq!(pixels.color_depth(), ColorDepth::GrayScale);
+        assert_eq!((pixels.width(), pixels.height(), pixels.len()), (2, 2, 4));
+        assert_eq!(pixels.gray().unwrap()[2], GrayA8{value: 30, alpha: 128});
+        assert!(pixels.rgba().is_none());
+        assert!(pixels.indices().is_none());
+        assert_eq!(pixels.row(1), Some(&[30, 128, 40, 0][..]));
+        assert!(pixels.row(2).is_none());
+        assert_eq!(pixels.rows().collect::<Vec<_>>(), [&[10, 255, 20, 255][..], &[30, 128, 40, 0][..]]);
+
+        let test_bytes = include_bytes!("../test.ase");
+        let ase = Ase::new(test_bytes).unwrap();
+        let pixels = ase.resolve_cel(0, 0).unwrap().pixels();
+        let rgba = pixels.rgba().unwrap();
+        assert_eq!(rgba.len(), pixels.len());
+        assert_eq!(rgba[1], Rgba8::new(&pixels.as_bytes()[4..8]));
+    }
+
+    #[test]
+    fn test_lazy_compressed_cel() {
+        let red = [255, 0, 0, 255];
+        let test_bytes = test_util::file(2, 1, 32, &[
+            test_util::frame(100, &[
+                test_util::layer("Layer", 1, 0, 0, 0, 255),
+                test_util::compressed_cel(0, 0, 0, 2, 1, &red.repeat(2)),
+            ]),
+            test_util::frame(100, &[
+                test_util::linked_cel(0, 0, 0, 0),
+            ]),
+        ]);
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        let cel = match ase.cel(0, 0) {
+            Some(Cel::Compressed(cel)) => cel,
+            other => panic!("expected a compressed cel, got {:?}", other),
+        };
+
+        assert!(!cel.is_decoded());
+        assert_eq!((cel.width(), cel.height()), (2, 1));
+        assert_eq!(cel.compressed_data(), &test_util::compress(&red.repeat(2))[..]);
+
+        // the linked cel shares the decompressed pixels of the one it points at
+        let linked = ase.resolve_cel(1, 0).unwrap();
+        assert!(cel.is_decoded());
+        assert!(std::ptr::eq(linked, cel.decode().unwrap()));
+        assert_eq!(linked.pixels().rgba().unwrap(), [Rgba8::new(&red); 2]);
+        assert_eq!(ase.render_frame(1).unwrap().pixel(1, 0), Some(red));
+    }
+
+    // writes the sprite out and reads it back, checking that everything the model holds survived.
+    fn round_trip(ase: &Ase) -> Ase {
+        let mut bytes = Vec::new();
+        ase.write_to(&mut bytes).unwrap();
+        let read = Ase::new(&bytes).unwrap();
+        assert_eq!(read.header.file_size as usize, bytes.len());
+
+        let header = |h: &Header| (
+            // layer UUIDs aren't kept, so their flag is dropped
+            h.width, h.height, h.color_depth, h.frames, h.flags & !4, h.speed, h.pallette_entry,
+            h.number_of_colors, h.pixel_width, h.pixel_height,
+        );
+        assert_eq!(header(&read.header), header(&ase.header));
+        assert_eq!(read.layers(), ase.layers());
+        assert_eq!(read.palette(), ase.palette());
+        assert_eq!(read.tags(), ase.tags());
+        assert_eq!(read.slices(), ase.slices());
+        assert_eq!(read.color_profile(), ase.color_profile());
+
+        let files = |ase: &Ase| ase.external_files().iter()
+            .map(|file| (file.id, file.file_type, file.name.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(files(&read), files(ase));
+
+        let tilesets = |ase: &Ase| ase.tilesets().iter()
+            .map(|t| (
+                (t.id, t.flags, t.tile_count, t.tile_width, t.tile_height, t.base_index, t.name.clone()),
+                t.external.map(|external| (external.file_id, external.tileset_id)),
+                (0..t.tile_count).map(|id| t.tile(id).cloned()).collect::<Vec<_>>(),
+                (t.user_data.clone(), t.tile_user_data.clone()),
+            ))
+            .collect::<Vec<_>>();
+        assert_eq!(tilesets(&read), tilesets(ase));
+
+        assert_eq!(read.frames.len(), ase.frames.len());
+        for (frame_index, (frame, original)) in read.frames.iter().zip(&ase.frames).enumerate() {
+            assert_eq!(frame.frame_duration, original.frame_duration);
+            assert_eq!(frame.cels().count(), original.cels().count());
+            for (cel, original) in frame.cels().zip(original.cels()) {
+                assert_eq!(cel.base(), original.base());
+                match (cel, original) {
+                    (Cel::Linked(cel), Cel::Linked(original)) => {
+                        assert_eq!(cel.frame_position(), original.frame_position());
+                    },
+                    // compressed data is written back untouched
+                    (Cel::Compressed(cel), Cel::Compressed(original)) => {
+                        assert_eq!(cel.compressed_data(), original.compressed_data());
+                    },
+                    (Cel::Compressed(_), Cel::Raw(_)) => (),
+                    (Cel::Tilemap(cel), Cel::Tilemap(original)) => {
+                        assert_eq!(cel.tiles(), original.tiles());
+                        assert_eq!((cel.width, cel.height, cel.bits_per_tile), (original.width, original.height, original.bits_per_tile));
+                    },
+                    other => panic!("cel type changed: {:?}", other),
+                }
+
+                let layer_index = cel.layer_index() as usize;
+                assert_eq!(
+                    read.resolve_cel(frame_index, layer_index).map(RawCel::pixels),
+                    ase.resolve_cel(frame_index, layer_index).map(RawCel::pixels),
+                );
+            }
+        }
+
+        read
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        let ase = Ase::new(include_bytes!("../test.ase")).unwrap();
+        let read = round_trip(&ase);
+        assert_eq!(read.render(), ase.render());
+
+        // once written, a sprite comes out the same every time
+        let (mut first, mut second) = (Vec::new(), Vec::new());
+        ase.write_to(&mut first).unwrap();
+        read.write_to(&mut second).unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_write_everything() {
+        let red = [255, 0, 0, 255];
+        let properties = test_util::properties(&[
+            (0, vec![
+                ("name", 0x000D, test_util::string("sword")),
+                ("area", 0x0010, [1i32, 2, 3, 4].iter().flat_map(|v| v.to_le_bytes()).collect()),
+                ("scale", 0x000B, 1.5f32.to_bits().to_le_bytes().to_vec()),
+                ("mixed", 0x0011, [
+                    test_util::dword(2), test_util::word(0),
+                    test_util::word(0x0003), vec![7],
+                    test_util::word(0x000D), test_util::string("x"),
+                ].concat()),
+                ("nested", 0x0012, [test_util::dword(1), test_util::string("on"), test_util::word(0x0001), vec![1]].concat()),
+            ]),
+            (3, vec![("id", 0x0009, 1u64.to_le_bytes().to_vec())]),
+        ]);
+
+        let empty = [0; 2 * 2 * 4];
+        let tiles = [empty.to_vec(), red.repeat(4)].concat();
+        let tile_ids: Vec<u8> = [0u32, 1, 1 | 0x2000_0000, 1 | 0x8000_0000].iter()
+            .flat_map(|id| id.to_le_bytes())
+            .collect();
+
+        let background = test_util::z_indexed(test_util::raw_cel(0, 0, 0, 4, 4, &red.repeat(16)), 2);
+        let mut test_bytes = test_util::file(4, 4, 32, &[
+            test_util::frame(100, &[
+                test_util::color_profile(1, 1, 0x18000, &[]),
+                test_util::external_files(&[(1, 2, "extension")]),
+                test_util::palette(2, 0, &[([0, 0, 0, 255], None), ([255, 0, 0, 255], Some("red"))]),
+                test_util::user_data(Some("sprite"), None, &[]),
+                test_util::tileset(0, 2, 2, 2, 1, "terrain", &tiles),
+                test_util::user_data(Some("terrain"), None, &[]),
+                test_util::user_data(None, None, &[]),
+                test_util::user_data(Some("lava"), None, &[]),
+                test_util::layer("Background", 9, 0, 0, 0, 255),
+                test_util::user_data(Some("layer"), Some([1, 2, 3, 4]), &[]),
+                test_util::layer("Group", 1, 1, 0, 0, 255),
+                test_util::layer("Multiply", 1, 0, 1, 1, 128),
+                test_util::tilemap_layer("Map", 0),
+                background,
+                test_util::user_data(None, None, &properties),
+                test_util::compressed_cel(2, -1, 1, 2, 1, &[0, 0, 255, 255, 0, 255, 0, 255]),
+                test_util::tilemap_cel(3, 0, 0, 2, 2, &tile_ids),
+                test_util::tags(&[
+                    (0, 1, 0, 0, [255, 0, 0], "idle"),
+                    (1, 1, 3, 2, [0, 0, 255], "attack"),
+                ]),
+                test_util::user_data(None, None, &[]),
+                test_util::user_data(Some("attack"), Some([5, 6, 7, 8]), &[]),
+                test_util::slice("hitbox", 0, &[vec![0, 1, 2, 8, 8], vec![1, -1, 2, 10, 8]]),
+                test_util::user_data(Some("slice"), None, &[]),
+                test_util::slice("button", 3, &[vec![0, 0, 0, 16, 16, 4, 4, 8, 8, 8, 15]]),
+            ]),
+            test_util::frame(150, &[
+                test_util::linked_cel(0, 0, 0, 0),
+                test_util::chunk(0x1234, &[1, 2, 3]),
+            ]),
+        ]);
+        test_bytes[14..18].copy_from_slice(&test_util::dword(1 | 4));
+
+        let ase = Ase::new(&test_bytes).unwrap();
+        let read = round_trip(&ase);
+        assert_eq!(read.header.flags, 1);
+        assert_eq!(read.cel(0, 0).unwrap().z_index(), 2);
+        assert_eq!(read.render_frame(0).unwrap().data(), ase.render_frame(0).unwrap().data());
+        assert_eq!(read.cel(0, 0).unwrap().user_data().unwrap().properties[&3]["id"], Value::U64(1));
+        assert!(matches!(read.diagnostics().warnings(), [Warning::UnknownChunk{chunk_type: 0x1234, ..}]));
+        assert!(matches!(read.frames[1].chunks[..], [Chunk::Unknown{chunk_type: 0x1234, ref data}] if data == &[1, 2, 3]));
+    }
 }
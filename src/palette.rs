@@ -0,0 +1,119 @@
+use std::ops::Index;
+use std::slice;
+
+use crate::{Chunk, OldPallettePacket, PalletteEntry, UserData};
+
+/// The sprite's color palette, built up from every palette chunk in the file. Indexed pixels
+/// point into this list.
+#[derive(Debug, Default, PartialEq)]
+pub struct Palette {
+    entries: Vec<PalletteEntry>,
+    user_data: Option<UserData>,
+}
+
+impl Palette {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&PalletteEntry> {
+        self.entries.get(index)
+    }
+
+    /// The user data attached to the palette, which Aseprite uses for the sprite's own user data.
+    pub fn user_data(&self) -> Option<&UserData> {
+        self.user_data.as_ref()
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, PalletteEntry> {
+        self.entries.iter()
+    }
+
+    // builds the palette from every palette chunk in the file. The old palette chunks are only
+    // used when the file doesn't have any new ones, since Aseprite writes both for the benefit
+    // of older readers.
+    pub(crate) fn from_chunks<'a, I>(chunks: I) -> Palette
+    where
+        I: Iterator<Item = &'a Chunk> + Clone,
+    {
+        let mut palette = Palette::default();
+        let has_new = chunks.clone().any(|chunk| matches!(chunk, Chunk::Pallette{..}));
+        for chunk in chunks {
+            match chunk {
+                Chunk::Pallette{..} => palette.merge(chunk),
+                Chunk::OldPallette{packets} if !has_new => palette.merge_old(packets, |c| c),
+                Chunk::OtherOldPallette{packets} if !has_new => palette.merge_old(packets, scale_6bits),
+                _ => (),
+            }
+        }
+
+        palette
+    }
+
+    // applies a palette chunk on top of what's been seen so far. Each chunk declares the new size
+    // of the palette and only carries the entries that changed.
+    fn merge(&mut self, chunk: &Chunk) {
+        if let Chunk::Pallette{size, first_color_index, entries, user_data, ..} = chunk {
+            if user_data.is_some() {
+                self.user_data = user_data.clone();
+            }
+
+            self.entries.resize_with(*size as usize, PalletteEntry::default);
+            let first = *first_color_index as usize;
+            for (i, entry) in entries.iter().enumerate() {
+                self.set(first + i, entry.clone());
+            }
+        }
+    }
+
+    fn merge_old(&mut self, packets: &[OldPallettePacket], scale: fn(u8) -> u8) {
+        let mut index = 0;
+        for packet in packets {
+            index += packet.entries_to_skip as usize;
+            for color in &packet.colors {
+                self.set(index, PalletteEntry{
+                    red: scale(color[0]),
+                    green: scale(color[1]),
+                    blue: scale(color[2]),
+                    alpha: 255,
+                    ..PalletteEntry::default()
+                });
+                index += 1;
+            }
+        }
+    }
+
+    fn set(&mut self, index: usize, entry: PalletteEntry) {
+        if index >= self.entries.len() {
+            self.entries.resize_with(index + 1, PalletteEntry::default);
+        }
+
+        self.entries[index] = entry;
+    }
+}
+
+// maps a 0-63 color component onto 0-255 the same way Aseprite does.
+fn scale_6bits(value: u8) -> u8 {
+    (value << 2) | (value >> 4)
+}
+
+impl Index<usize> for Palette {
+    type Output = PalletteEntry;
+
+    fn index(&self, index: usize) -> &PalletteEntry {
+        &self.entries[index]
+    }
+}
+
+impl<'a> IntoIterator for &'a Palette {
+    type Item = &'a PalletteEntry;
+    type IntoIter = slice::Iter<'a, PalletteEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
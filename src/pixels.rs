@@ -0,0 +1,144 @@
+//! Image data kept as one contiguous buffer of bytes, laid out the same way the file stores it,
+//! and tagged once with its color depth.
+
+use std::fmt;
+use std::mem;
+use std::slice;
+
+use crate::ColorDepth;
+
+/// An 8-bit straight (not premultiplied) RGBA color.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rgba8 {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Rgba8 {
+    // raw is expected to hold at least 4 bytes.
+    pub(crate) fn new(raw: &[u8]) -> Rgba8 {
+        Rgba8{
+            r: raw[0],
+            g: raw[1],
+            b: raw[2],
+            a: raw[3],
+        }
+    }
+}
+
+/// An 8-bit grayscale value with alpha.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GrayA8 {
+    pub value: u8,
+    pub alpha: u8,
+}
+
+// The typed views below reinterpret the byte buffer in place, which is only sound while both
+// pixel types are plain bytes with no padding and no alignment requirement.
+const _: () = assert!(mem::size_of::<Rgba8>() == 4 && mem::align_of::<Rgba8>() == 1);
+const _: () = assert!(mem::size_of::<GrayA8>() == 2 && mem::align_of::<GrayA8>() == 1);
+
+/// A block of pixels, row by row from the top. How the bytes are read depends on the color
+/// depth: 4 bytes of RGBA, 2 bytes of gray and alpha, or a 1 byte palette index per pixel.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Pixels {
+    color_depth: ColorDepth,
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl Pixels {
+    // data is expected to hold exactly width x height pixels of the given depth.
+    pub(crate) fn new(color_depth: ColorDepth, width: usize, height: usize, data: Vec<u8>) -> Pixels {
+        debug_assert_eq!(data.len(), color_depth.data_size(width, height));
+        Pixels{
+            color_depth,
+            width,
+            height,
+            data,
+        }
+    }
+
+    pub fn color_depth(&self) -> ColorDepth {
+        self.color_depth
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The number of pixels.
+    pub fn len(&self) -> usize {
+        self.width * self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The pixel data as stored in the file.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The pixels as RGBA colors, if that's their color depth.
+    pub fn rgba(&self) -> Option<&[Rgba8]> {
+        match self.color_depth {
+            // Rgba8 is 4 plain bytes with an alignment of 1, and data holds 4 bytes per pixel.
+            ColorDepth::RGBA => Some(unsafe {
+                slice::from_raw_parts(self.data.as_ptr() as *const Rgba8, self.len())
+            }),
+            _ => None,
+        }
+    }
+
+    /// The pixels as gray values with alpha, if that's their color depth.
+    pub fn gray(&self) -> Option<&[GrayA8]> {
+        match self.color_depth {
+            // GrayA8 is 2 plain bytes with an alignment of 1, and data holds 2 bytes per pixel.
+            ColorDepth::GrayScale => Some(unsafe {
+                slice::from_raw_parts(self.data.as_ptr() as *const GrayA8, self.len())
+            }),
+            _ => None,
+        }
+    }
+
+    /// The pixels as palette indices, if that's their color depth.
+    pub fn indices(&self) -> Option<&[u8]> {
+        match self.color_depth {
+            ColorDepth::Indexed => Some(&self.data),
+            _ => None,
+        }
+    }
+
+    /// The bytes of the row at the given y, counting down from the top.
+    pub fn row(&self, y: usize) -> Option<&[u8]> {
+        let size = self.color_depth.data_size(self.width, 1);
+        self.data.get(y * size..(y + 1) * size)
+    }
+
+    /// Iterates over the bytes of every row from top to bottom.
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        (0..self.height).filter_map(move |y| self.row(y))
+    }
+}
+
+impl fmt::Debug for Pixels {
+    // the pixel data itself is far too long to be useful here.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pixels")
+            .field("color_depth", &self.color_depth)
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
@@ -0,0 +1,186 @@
+use std::io::{self, Read};
+
+use crate::{
+    Ase, AseError, Chunk, Diagnostics, Frame, Header, Raw, Result, CHUNK_HEADER_SIZE,
+    FRAME_HEADER_SIZE, HEADER_SIZE,
+};
+
+// Reads a file a piece at a time. Only the piece being parsed is buffered, and the buffer is
+// reused from one piece to the next, so memory stays proportional to the largest chunk rather
+// than the whole file.
+struct Source<R> {
+    reader: R,
+    // where the reader is in the file.
+    offset: usize,
+    // where the buffered piece starts in the file.
+    start: usize,
+    buffer: Vec<u8>,
+}
+
+impl<R: Read> Source<R> {
+    fn new(reader: R) -> Source<R> {
+        Source{
+            reader,
+            offset: 0,
+            start: 0,
+            buffer: Vec::new(),
+        }
+    }
+
+    // starts buffering a new piece at the current position.
+    fn begin(&mut self) {
+        self.buffer.clear();
+        self.start = self.offset;
+    }
+
+    // appends the next len bytes to the piece. The buffer only grows as data actually arrives,
+    // so a corrupt size can't make it allocate more than the file holds.
+    fn fill(&mut self, len: usize) -> Result<()> {
+        let before = self.buffer.len();
+        (&mut self.reader).take(len as u64)
+            .read_to_end(&mut self.buffer)
+            .map_err(|source| AseError::Io{
+                offset: self.offset,
+                source,
+            })?;
+
+        let read = self.buffer.len() - before;
+        if read < len {
+            return Err(AseError::Truncated{
+                offset: self.offset,
+                needed: len,
+                available: read,
+            });
+        }
+
+        self.offset += len;
+        Ok(())
+    }
+
+    // skips over bytes that aren't needed.
+    fn skip(&mut self, len: usize) -> Result<()> {
+        let skipped = io::copy(&mut (&mut self.reader).take(len as u64), &mut io::sink())
+            .map_err(|source| AseError::Io{
+                offset: self.offset,
+                source,
+            })? as usize;
+
+        if skipped < len {
+            return Err(AseError::Truncated{
+                offset: self.offset,
+                needed: len,
+                available: skipped,
+            });
+        }
+
+        self.offset += len;
+        Ok(())
+    }
+
+    fn raw(&self) -> Raw<'_> {
+        Raw{
+            bytes: &self.buffer,
+            offset: self.start,
+        }
+    }
+
+    fn read_header(&mut self) -> Result<Header> {
+        self.begin();
+        self.fill(HEADER_SIZE)?;
+        Header::parse(self.raw())
+    }
+
+    // reads a frame one chunk at a time.
+    fn read_frame(&mut self, header: &Header, diagnostics: &mut Diagnostics) -> Result<Frame> {
+        let start = self.offset;
+        self.begin();
+        self.fill(FRAME_HEADER_SIZE)?;
+        let mut frame = Frame::parse_header(self.raw())?;
+        let end = start + frame.size as usize;
+        let mut user_data_target = None;
+        for _ in 0..frame.chunk_count() {
+            self.begin();
+            self.fill(CHUNK_HEADER_SIZE)?;
+            let size = self.raw().dword(0)? as usize;
+            if self.start + size > end {
+                return Err(AseError::Truncated{
+                    offset: self.start,
+                    needed: size,
+                    available: end.saturating_sub(self.start),
+                });
+            }
+
+            self.fill(size.saturating_sub(CHUNK_HEADER_SIZE))?;
+            let (chunk, _) = Chunk::parse(header, self.raw())?;
+            frame.add_chunk(chunk, self.start, &mut user_data_target, diagnostics);
+        }
+
+        // frames can be padded past their last chunk
+        self.skip(end.saturating_sub(self.offset))?;
+        Ok(frame)
+    }
+}
+
+/// Reads a sprite one frame at a time, so long animations can be streamed without holding more
+/// than a frame in memory. Works over any reader, including a byte slice.
+///
+/// Frames come out as they're stored in the file, with layers and other sprite-wide chunks left
+/// in the first frame's chunks.
+pub struct FrameReader<R> {
+    source: Source<R>,
+    header: Header,
+    next: u16,
+    diagnostics: Diagnostics,
+}
+
+impl<R: Read> FrameReader<R> {
+    /// Reads the file header, leaving the frames to be read by iterating.
+    pub fn new(reader: R) -> Result<FrameReader<R>> {
+        let mut source = Source::new(reader);
+        let header = source.read_header()?;
+        Ok(FrameReader{
+            source,
+            header,
+            next: 0,
+            diagnostics: Diagnostics::default(),
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Warnings from the frames read so far.
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+}
+
+impl<R: Read> Iterator for FrameReader<R> {
+    type Item = Result<Frame>;
+
+    // After an error there's no telling where the next frame starts, so iteration stops.
+    fn next(&mut self) -> Option<Result<Frame>> {
+        if self.next >= self.header.frames {
+            return None;
+        }
+
+        let frame = self.source.read_frame(&self.header, &mut self.diagnostics);
+        self.next = match frame {
+            Ok(_) => self.next + 1,
+            Err(_) => self.header.frames,
+        };
+
+        Some(frame)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some((self.header.frames - self.next) as usize))
+    }
+}
+
+pub(crate) fn read_ase<R: Read>(reader: R) -> Result<Ase> {
+    let mut reader = FrameReader::new(reader)?;
+    let frames = reader.by_ref().collect::<Result<Vec<Frame>>>()?;
+    Ok(Ase::from_frames(reader.header, frames, reader.diagnostics))
+}
@@ -0,0 +1,565 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::iter;
+
+use crate::{
+    blend, Ase, Cel, Diagnostics, Layer, LayerFlags, LayerNode, Palette, Pixels, RawCel, Rgba8, Tile,
+    Warning,
+};
+
+/// A rendered frame as straight (not premultiplied) 8-bit RGBA, row by row from the top.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl Image {
+    // a fully transparent image.
+    pub(crate) fn new(width: usize, height: usize) -> Image {
+        Image{
+            width,
+            height,
+            data: vec![0; width * height * 4],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The RGBA bytes of every pixel.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn into_raw(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Returns the RGBA color at the given position.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<[u8; 4]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        let idx = (y * self.width + x) * 4;
+        let mut pixel = [0; 4];
+        pixel.copy_from_slice(&self.data[idx..idx + 4]);
+        Some(pixel)
+    }
+}
+
+/// A rendered frame along with how long it's shown for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedFrame {
+    pub image: Image,
+    /// In milliseconds.
+    pub duration: u16,
+}
+
+/// A rendered frame of an indexed sprite, kept as palette indices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedImage {
+    width: usize,
+    height: usize,
+    indices: Vec<u8>,
+    palette: Vec<[u8; 4]>,
+    transparent_index: u8,
+}
+
+impl IndexedImage {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// One palette index per pixel, row by row from the top.
+    pub fn indices(&self) -> &[u8] {
+        &self.indices
+    }
+
+    /// The sprite's palette as RGBA colors.
+    pub fn palette(&self) -> &[[u8; 4]] {
+        &self.palette
+    }
+
+    /// The index of pixels nothing was drawn on.
+    pub fn transparent_index(&self) -> u8 {
+        self.transparent_index
+    }
+
+    /// Returns the palette index at the given position.
+    pub fn index(&self, x: usize, y: usize) -> Option<u8> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        Some(self.indices[y * self.width + x])
+    }
+}
+
+/// A rendered frame of a grayscale sprite, with a value and an alpha byte per pixel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrayImage {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl GrayImage {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The value and alpha bytes of every pixel.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_raw(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Returns the value and alpha at the given position.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<[u8; 2]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        let idx = (y * self.width + x) * 2;
+        Some([self.data[idx], self.data[idx + 1]])
+    }
+}
+
+/// Controls what goes into a render. Layers are picked by name, or by their full path through
+/// their groups like "body/arm/left"; picking a group picks everything inside it.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    pub(crate) frame: usize,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    show: Vec<String>,
+    hide: Vec<String>,
+    skip_reference_layers: bool,
+    include_background: bool,
+    background_color: Option<[u8; 4]>,
+    srgb: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions{
+            frame: 0,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            show: Vec::new(),
+            hide: Vec::new(),
+            skip_reference_layers: false,
+            include_background: true,
+            background_color: None,
+            srgb: false,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Renders the first frame with every visible layer, the same as `Ase::render`.
+    pub fn new() -> RenderOptions {
+        RenderOptions::default()
+    }
+
+    /// The index of the frame to render.
+    pub fn frame(mut self, frame_index: usize) -> RenderOptions {
+        self.frame = frame_index;
+        self
+    }
+
+    /// Only renders the given layer and the ones picked by other calls to include. Everything
+    /// is included until this is called.
+    pub fn include<S: Into<String>>(mut self, layer: S) -> RenderOptions {
+        self.include.push(layer.into());
+        self
+    }
+
+    /// Leaves out the given layer, even if it was included.
+    pub fn exclude<S: Into<String>>(mut self, layer: S) -> RenderOptions {
+        self.exclude.push(layer.into());
+        self
+    }
+
+    /// Renders the given layer even if it or a group containing it is hidden.
+    pub fn show<S: Into<String>>(mut self, layer: S) -> RenderOptions {
+        self.show.push(layer.into());
+        self
+    }
+
+    /// Hides the given layer even if it's visible. Takes priority over show.
+    pub fn hide<S: Into<String>>(mut self, layer: S) -> RenderOptions {
+        self.hide.push(layer.into());
+        self
+    }
+
+    /// Whether to leave out reference layers, the way Aseprite does when exporting. They're
+    /// rendered by default.
+    pub fn skip_reference_layers(mut self, skip: bool) -> RenderOptions {
+        self.skip_reference_layers = skip;
+        self
+    }
+
+    /// Whether to render the background layer. It's rendered by default.
+    pub fn include_background(mut self, include: bool) -> RenderOptions {
+        self.include_background = include;
+        self
+    }
+
+    /// Fills the image with the given RGBA color before drawing any layers.
+    pub fn background_color(mut self, color: [u8; 4]) -> RenderOptions {
+        self.background_color = Some(color);
+        self
+    }
+
+    /// Whether to convert the image from the sprite's color profile into sRGB. The background
+    /// color is converted along with everything else. Profiles that can't be understood are
+    /// left as is.
+    pub fn srgb(mut self, srgb: bool) -> RenderOptions {
+        self.srgb = srgb;
+        self
+    }
+
+    // whether the layer, or any group containing it, is one of the given layers.
+    fn picks(patterns: &[String], node: LayerNode) -> bool {
+        iter::once(node)
+            .chain(node.ancestors())
+            .any(|node| RenderOptions::names(patterns, node))
+    }
+
+    // whether the layer itself is one of the given layers.
+    fn names(patterns: &[String], node: LayerNode) -> bool {
+        patterns.iter().any(|pattern| {
+            pattern == node.layer().name() || (pattern.contains('/') && *pattern == node.path())
+        })
+    }
+
+    // whether the layer ends up drawn. Every group containing it has to be visible too, with
+    // show and hide overriding the layers' own flags. Showing a layer also shows every group
+    // it's in.
+    fn draws(&self, node: LayerNode) -> bool {
+        let flags = node.layer().flags();
+        let mut shown = false;
+        let visible = iter::once(node).chain(node.ancestors()).all(|node| {
+            shown |= RenderOptions::names(&self.show, node);
+            !RenderOptions::names(&self.hide, node) && (shown || node.layer().is_visible())
+        });
+
+        visible
+            && (self.include.is_empty() || RenderOptions::picks(&self.include, node))
+            && !RenderOptions::picks(&self.exclude, node)
+            && !(self.skip_reference_layers && flags.contains(LayerFlags::REFERENCE))
+            && (self.include_background || !flags.contains(LayerFlags::BACKGROUND))
+    }
+}
+
+// composites every layer picked by the options from bottom to top.
+pub(crate) fn render_frame(ase: &Ase, options: &RenderOptions, diagnostics: &mut Diagnostics) -> Image {
+    let mut image = Image::new(ase.header.width as usize, ase.header.height as usize);
+    if let Some(color) = options.background_color {
+        for pixel in image.data.chunks_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    let tree = ase.layer_tree();
+    let drawn = drawn_layers(ase, options);
+    if ase.header.composites_groups() {
+        draw_layers(ase, options.frame, &drawn, tree.roots(), &mut image, diagnostics);
+    } else {
+        let layers = tree.iter().filter(|node| !node.is_group());
+        draw_layers(ase, options.frame, &drawn, layers, &mut image, diagnostics);
+    }
+
+    if options.srgb {
+        ase.color_profile.to_srgb(&mut image.data);
+    }
+
+    image
+}
+
+// draws the layers onto the image from bottom to top. A group's layers are drawn onto an image
+// of their own first, which then goes onto this one with the group's blend mode and opacity.
+fn draw_layers<'a>(
+    ase: &Ase,
+    frame: usize,
+    drawn: &[bool],
+    nodes: impl Iterator<Item = LayerNode<'a>>,
+    image: &mut Image,
+    diagnostics: &mut Diagnostics,
+) {
+    let (width, height) = (image.width, image.height);
+    for node in draw_order(ase, frame, nodes.filter(|node| drawn[node.index()])) {
+        let layer = node.layer();
+        if node.is_group() {
+            let opacity = layer_opacity(ase, layer);
+            let mut group = Image::new(width, height);
+            draw_layers(ase, frame, drawn, node.children(), &mut group, diagnostics);
+            let pixels = image.data.chunks_exact_mut(4).zip(group.data.chunks_exact(4));
+            for (pixel, src) in pixels {
+                let result = blend::blend(layer.blend_mode(), &Rgba8::new(pixel), &Rgba8::new(src), opacity);
+                pixel.copy_from_slice(&[result.r, result.g, result.b, result.a]);
+            }
+
+            continue;
+        }
+
+        // the background layer is opaque, so the transparent index is just another color there
+        let transparent_index = if layer.flags().contains(LayerFlags::BACKGROUND) {
+            None
+        } else {
+            Some(ase.header.transparent_index())
+        };
+
+        for cel in layer_cels(ase, frame, node.index(), diagnostics) {
+            let opacity = blend::mul_un8(cel.base.opacity, layer_opacity(ase, layer));
+            let colors = to_rgba(&cel.pixels, &ase.palette, transparent_index);
+            for (src, dst, len) in cel.visible_runs(width, height) {
+                let row = image.data[dst * 4..(dst + len) * 4].chunks_exact_mut(4);
+                for (pixel, src) in row.zip(&colors[src..src + len]) {
+                    let result = blend::blend(layer.blend_mode(), &Rgba8::new(pixel), src, opacity);
+                    pixel.copy_from_slice(&[result.r, result.g, result.b, result.a]);
+                }
+            }
+        }
+    }
+}
+
+// stacks the indices of every visible layer. There's no blending in indexed mode, so each pixel
+// takes the index of the topmost layer that isn't transparent there.
+pub(crate) fn render_frame_indexed(ase: &Ase, frame_index: usize) -> IndexedImage {
+    let width = ase.header.width as usize;
+    let height = ase.header.height as usize;
+    let transparent_index = ase.header.transparent_index();
+    let mut indices = vec![transparent_index; width * height];
+    for (layer, cel) in layers_to_draw(ase, &RenderOptions::new().frame(frame_index)) {
+        let background = layer.flags().contains(LayerFlags::BACKGROUND);
+        let cel_indices = match cel.pixels.indices() {
+            Some(cel_indices) => cel_indices,
+            None => continue,
+        };
+
+        for (src, dst, len) in cel.visible_runs(width, height) {
+            let (src, dst) = (&cel_indices[src..src + len], &mut indices[dst..dst + len]);
+            if background {
+                dst.copy_from_slice(src);
+            } else {
+                for (dst, &src) in dst.iter_mut().zip(src) {
+                    if src != transparent_index {
+                        *dst = src;
+                    }
+                }
+            }
+        }
+    }
+
+    IndexedImage{
+        width,
+        height,
+        indices,
+        palette: ase.palette.iter()
+            .map(|entry| [entry.red, entry.green, entry.blue, entry.alpha])
+            .collect(),
+        transparent_index,
+    }
+}
+
+// composites every visible layer the same way render_frame does, keeping only one channel. Gray
+// in, gray out: every blend mode keeps the channels equal.
+pub(crate) fn render_frame_grayscale(ase: &Ase, frame_index: usize) -> GrayImage {
+    let options = RenderOptions::new().frame(frame_index);
+    let image = render_frame(ase, &options, &mut Diagnostics::default());
+    GrayImage{
+        width: image.width,
+        height: image.height,
+        data: image.data.chunks_exact(4).flat_map(|pixel| [pixel[0], pixel[3]]).collect(),
+    }
+}
+
+// the layer's opacity, if the file says it's valid.
+fn layer_opacity(ase: &Ase, layer: &Layer) -> u8 {
+    if ase.header.has_layer_opacity() {
+        layer.opacity()
+    } else {
+        255
+    }
+}
+
+// the layers the options pick, along with every group containing one of them, by layer index.
+fn drawn_layers(ase: &Ase, options: &RenderOptions) -> Vec<bool> {
+    let mut drawn = vec![false; ase.layers.len()];
+    for node in ase.layer_tree().iter().filter(|node| !node.is_group() && options.draws(*node)) {
+        for node in iter::once(node).chain(node.ancestors()) {
+            drawn[node.index()] = true;
+        }
+    }
+
+    drawn
+}
+
+// the layers that end up in the frame along with their cels, from bottom to top, leaving out
+// groups.
+fn layers_to_draw<'a>(ase: &'a Ase, options: &RenderOptions) -> Vec<(&'a Layer, Cow<'a, RawCel>)> {
+    let tree = ase.layer_tree();
+    let drawn = drawn_layers(ase, options);
+    let layers = tree.iter().filter(|node| drawn[node.index()] && !node.is_group());
+    let mut diagnostics = Diagnostics::default();
+    draw_order(ase, options.frame, layers).into_iter()
+        .map(|node| node.index())
+        .flat_map(|index| {
+            layer_cels(ase, options.frame, index, &mut diagnostics).into_iter()
+                .map(move |cel| (&ase.layers[index], cel))
+        })
+        .collect()
+}
+
+// puts the layers in the order their cels are drawn in the frame. A cel's z-index moves it that
+// many layers up or down, and cels landing in the same spot go from the lowest z-index up.
+fn draw_order<'a>(ase: &Ase, frame: usize, nodes: impl Iterator<Item = LayerNode<'a>>) -> Vec<LayerNode<'a>> {
+    let mut nodes: Vec<LayerNode> = nodes.collect();
+    nodes.sort_by_key(|node| {
+        let z_index = ase.cel(frame, node.index()).map_or(0, Cel::z_index) as i64;
+        (node.index() as i64 + z_index, z_index)
+    });
+
+    nodes
+}
+
+// what a layer draws in the frame as cels placed on the canvas. That's its own cel, or for a
+// tilemap a cel for every tile that lands on the canvas.
+fn layer_cels<'a>(ase: &'a Ase, frame: usize, layer: usize, diagnostics: &mut Diagnostics) -> Vec<Cow<'a, RawCel>> {
+    let (width, height) = (ase.header.width as usize, ase.header.height as usize);
+    let tilemap = match ase.unlinked_cel(frame, layer) {
+        Some(Cel::Tilemap(tilemap)) => tilemap,
+        _ => {
+            let cel = match ase.resolve_cel(frame, layer) {
+                Some(cel) => cel,
+                None => return Vec::new(),
+            };
+
+            if cel.is_clipped(width, height) {
+                diagnostics.warn(Warning::ClippedCel{frame, layer});
+            }
+
+            return vec![Cow::Borrowed(cel)];
+        },
+    };
+
+    let tileset = ase.layers[layer].tileset_index()
+        .and_then(|id| ase.tilesets.iter().find(|tileset| tileset.id == id))
+        .filter(|tileset| tileset.has_embedded_tiles());
+    let tileset = match tileset {
+        Some(tileset) => tileset,
+        None => {
+            diagnostics.warn(Warning::MissingTiles{frame, layer});
+            return Vec::new();
+        },
+    };
+
+    let (tile_width, tile_height) = (tileset.tile_width as i64, tileset.tile_height as i64);
+    let (left, top) = (tilemap.base.x as i64, tilemap.base.y as i64);
+    let (right, bottom) = (left + tilemap.width as i64 * tile_width, top + tilemap.height as i64 * tile_height);
+    if left < 0 || top < 0 || right > width as i64 || bottom > height as i64 {
+        diagnostics.warn(Warning::ClippedCel{frame, layer});
+    }
+
+    let mut cels = Vec::new();
+    for (position, tile) in tilemap.tiles().iter().enumerate() {
+        let column = (position % tilemap.width as usize) as i64;
+        let row = (position / tilemap.width as usize) as i64;
+        let (x, y) = (left + column * tile_width, top + row * tile_height);
+        let on_canvas = x < width as i64 && y < height as i64 && x + tile_width > 0 && y + tile_height > 0;
+        let (pixels, x, y) = match (tileset.tile(tile.id), i16::try_from(x), i16::try_from(y)) {
+            (Some(pixels), Ok(x), Ok(y)) if on_canvas => (pixels, x, y),
+            _ => continue,
+        };
+
+        let mut base = tilemap.base.clone();
+        base.x = x;
+        base.y = y;
+        let pixels = flip_tile(pixels, tile);
+        cels.push(Cow::Owned(RawCel{
+            base,
+            width: pixels.width() as u16,
+            height: pixels.height() as u16,
+            pixels,
+        }));
+    }
+
+    cels
+}
+
+// applies a tile's flips to its pixels. The diagonal flip goes first and swaps the axes, then
+// the tile is mirrored along x and y.
+fn flip_tile(pixels: &Pixels, tile: &Tile) -> Pixels {
+    if !(tile.x_flip || tile.y_flip || tile.diagonal_flip) {
+        return pixels.clone();
+    }
+
+    let (width, height) = (pixels.width(), pixels.height());
+    let (flipped_width, flipped_height) = if tile.diagonal_flip { (height, width) } else { (width, height) };
+    let pixel_size = pixels.color_depth().data_size(1, 1);
+    let bytes = pixels.as_bytes();
+    let mut data = Vec::with_capacity(bytes.len());
+    for y in 0..flipped_height {
+        for x in 0..flipped_width {
+            let x = if tile.x_flip { flipped_width - 1 - x } else { x };
+            let y = if tile.y_flip { flipped_height - 1 - y } else { y };
+            let (x, y) = if tile.diagonal_flip { (y, x) } else { (x, y) };
+            let idx = (y * width + x) * pixel_size;
+            data.extend_from_slice(&bytes[idx..idx + pixel_size]);
+        }
+    }
+
+    Pixels::new(pixels.color_depth(), flipped_width, flipped_height, data)
+}
+
+// resolves pixels of any color depth to the colors they show up as, borrowing them when they're
+// RGBA already. Indexed pixels outside the palette are transparent, like in Aseprite.
+fn to_rgba<'a>(pixels: &'a Pixels, palette: &Palette, transparent_index: Option<u8>) -> Cow<'a, [Rgba8]> {
+    if let Some(rgba) = pixels.rgba() {
+        return Cow::Borrowed(rgba);
+    }
+
+    if let Some(gray) = pixels.gray() {
+        return gray.iter()
+            .map(|pixel| Rgba8::new(&[pixel.value, pixel.value, pixel.value, pixel.alpha]))
+            .collect();
+    }
+
+    // every index is looked up once rather than once per pixel
+    let colors: Vec<Rgba8> = (0..=u8::MAX)
+        .map(|index| match palette.get(index as usize) {
+            Some(_) if Some(index) == transparent_index => Rgba8::default(),
+            Some(entry) => Rgba8::new(&[entry.red, entry.green, entry.blue, entry.alpha]),
+            None => Rgba8::default(),
+        })
+        .collect();
+
+    pixels.indices().unwrap_or_default().iter()
+        .map(|&index| colors[index as usize])
+        .collect()
+}
@@ -0,0 +1,108 @@
+use crate::{Raw, Result, UserData};
+
+const NINE_PATCH_FLAG: u32 = 1;
+const PIVOT_FLAG: u32 = 2;
+
+/// A named region of the sprite, used for things like hitboxes, nine-patches and origin points.
+/// The region can change over the course of an animation, so each change is stored as a key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slice {
+    pub flags: u32,
+    pub name: String,
+    pub keys: Vec<SliceKey>,
+    pub(crate) user_data: Option<UserData>,
+}
+
+impl Slice {
+    pub(crate) fn new(raw: Raw) -> Result<Slice> {
+        let key_count = raw.dword(0)?;
+        let flags = raw.dword(4)?;
+        // 4 reserved bytes
+        let (name, name_size) = raw.string(12)?;
+        let mut offset = 12 + name_size;
+        // every key takes at least 20 bytes
+        let mut keys = Vec::with_capacity(raw.check_count(offset, key_count as usize, 20)?);
+        for _ in 0..key_count {
+            let (key, size) = SliceKey::new(flags, raw.rest(offset)?)?;
+            offset += size;
+            keys.push(key);
+        }
+
+        Ok(Slice{
+            flags,
+            name,
+            keys,
+            user_data: None,
+        })
+    }
+
+    pub fn user_data(&self) -> Option<&UserData> {
+        self.user_data.as_ref()
+    }
+
+    /// Whether the keys carry a nine-patch center.
+    pub fn has_nine_patch(&self) -> bool {
+        self.flags & NINE_PATCH_FLAG != 0
+    }
+
+    /// Whether the keys carry a pivot point.
+    pub fn has_pivot(&self) -> bool {
+        self.flags & PIVOT_FLAG != 0
+    }
+
+    /// Returns the key in effect for the given frame, which is the last key starting on or before
+    /// it.
+    pub fn key_for_frame(&self, frame: u32) -> Option<&SliceKey> {
+        self.keys.iter()
+            .filter(|key| key.frame_number <= frame)
+            .max_by_key(|key| key.frame_number)
+    }
+}
+
+/// The slice's bounds starting at frame_number. The center and pivot fields are only set when the
+/// slice's flags say they're present and are zero otherwise.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SliceKey {
+    pub frame_number: u32,
+    pub x: i64,
+    pub y: i64,
+    pub width: u32,
+    pub height: u32,
+    pub center_x: i64,
+    pub center_y: i64,
+    pub center_width: u32,
+    pub center_height: u32,
+    pub pivot_x: i64,
+    pub pivot_y: i64,
+}
+
+impl SliceKey {
+    // returns the key along with the number of bytes it occupied.
+    fn new(flags: u32, raw: Raw) -> Result<(SliceKey, usize)> {
+        let mut key = SliceKey{
+            frame_number: raw.dword(0)?,
+            x: raw.long(4)? as i64,
+            y: raw.long(8)? as i64,
+            width: raw.dword(12)?,
+            height: raw.dword(16)?,
+            ..SliceKey::default()
+        };
+
+        let mut offset = 20;
+        if flags & NINE_PATCH_FLAG != 0 {
+            key.center_x = raw.long(offset)? as i64;
+            key.center_y = raw.long(offset+4)? as i64;
+            key.center_width = raw.dword(offset+8)?;
+            key.center_height = raw.dword(offset+12)?;
+            offset += 16;
+        }
+
+        if flags & PIVOT_FLAG != 0 {
+            key.pivot_x = raw.long(offset)? as i64;
+            key.pivot_y = raw.long(offset+4)? as i64;
+            offset += 8;
+        }
+
+        Ok((key, offset))
+    }
+}
@@ -0,0 +1,70 @@
+use std::convert::TryFrom;
+
+use crate::{Raw, Result, UserData};
+
+/// A named range of frames, which is how animations ("idle", "run", ...) are defined in a sprite.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag {
+    pub from_frame: u16,
+    pub to_frame: u16,
+    pub direction: LoopDirection,
+    /// How many times the animation plays. 0 means it repeats forever.
+    pub repeat: u16,
+    pub color: [u8; 3],
+    pub name: String,
+    pub(crate) user_data: Option<UserData>,
+}
+
+impl Tag {
+    // returns the tag along with the number of bytes it occupied.
+    pub(crate) fn new(raw: Raw) -> Result<(Tag, usize)> {
+        let direction = raw.byte(4)?;
+        let color = raw.get(13, 3)?;
+        let (name, name_size) = raw.string(17)?;
+        let tag = Tag{
+            from_frame: raw.word(0)?,
+            to_frame: raw.word(2)?,
+            direction: LoopDirection::try_from(direction)
+                .map_err(|value| raw.unknown(4, "loop direction", value))?,
+            repeat: raw.word(5)?,
+            // 6 reserved bytes
+            color: [color[0], color[1], color[2]],
+            // 1 extra byte
+            name,
+            user_data: None,
+        };
+
+        Ok((tag, 17 + name_size))
+    }
+
+    pub fn user_data(&self) -> Option<&UserData> {
+        self.user_data.as_ref()
+    }
+
+    /// The frames covered by this tag, in file order.
+    pub fn frames(&self) -> std::ops::RangeInclusive<usize> {
+        self.from_frame as usize..=self.to_frame as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopDirection {
+    Forward,
+    Reverse,
+    PingPong,
+    PingPongReverse,
+}
+
+impl TryFrom<u8> for LoopDirection {
+    type Error = u8;
+
+    fn try_from(raw: u8) -> std::result::Result<LoopDirection, u8> {
+        match raw {
+            0 => Ok(LoopDirection::Forward),
+            1 => Ok(LoopDirection::Reverse),
+            2 => Ok(LoopDirection::PingPong),
+            3 => Ok(LoopDirection::PingPongReverse),
+            _ => Err(raw),
+        }
+    }
+}
@@ -0,0 +1,357 @@
+//! Helpers for building small aseprite files by hand in tests.
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::Write;
+
+pub fn word(value: u16) -> Vec<u8> {
+    value.to_le_bytes().to_vec()
+}
+
+pub fn dword(value: u32) -> Vec<u8> {
+    value.to_le_bytes().to_vec()
+}
+
+pub fn string(value: &str) -> Vec<u8> {
+    let mut bytes = word(value.len() as u16);
+    bytes.extend_from_slice(value.as_bytes());
+    bytes
+}
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Builds a complete file out of the given frames.
+pub fn file(width: u16, height: u16, color_depth: u16, frames: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = vec![0; 128];
+    bytes[4..6].copy_from_slice(&word(0xA5E0));
+    bytes[6..8].copy_from_slice(&word(frames.len() as u16));
+    bytes[8..10].copy_from_slice(&word(width));
+    bytes[10..12].copy_from_slice(&word(height));
+    bytes[12..14].copy_from_slice(&word(color_depth));
+    // layer opacity is valid, and so is group opacity
+    bytes[14..18].copy_from_slice(&dword(1 | 2));
+    bytes[18..20].copy_from_slice(&word(100));
+    bytes[34] = 1;
+    bytes[35] = 1;
+    for frame in frames {
+        bytes.extend_from_slice(frame);
+    }
+
+    let size = bytes.len() as u32;
+    bytes[0..4].copy_from_slice(&dword(size));
+    bytes
+}
+
+pub fn frame(duration: u16, chunks: &[Vec<u8>]) -> Vec<u8> {
+    let body: Vec<u8> = chunks.concat();
+    let mut bytes = dword(16 + body.len() as u32);
+    bytes.extend(word(0xF1FA));
+    bytes.extend(word(chunks.len() as u16));
+    bytes.extend(word(duration));
+    bytes.extend(&[0, 0]);
+    bytes.extend(dword(chunks.len() as u32));
+    bytes.extend(body);
+    bytes
+}
+
+pub fn chunk(chunk_type: u16, body: &[u8]) -> Vec<u8> {
+    let mut bytes = dword(6 + body.len() as u32);
+    bytes.extend(word(chunk_type));
+    bytes.extend_from_slice(body);
+    bytes
+}
+
+pub fn layer(name: &str, flags: u16, layer_type: u16, child_level: u16, blend_mode: u16, opacity: u8) -> Vec<u8> {
+    let mut body = word(flags);
+    body.extend(word(layer_type));
+    body.extend(word(child_level));
+    body.extend(word(0));
+    body.extend(word(0));
+    body.extend(word(blend_mode));
+    body.push(opacity);
+    body.extend(&[0; 3]);
+    body.extend(string(name));
+    chunk(0x2004, &body)
+}
+
+fn cel_header(layer: u16, x: i16, y: i16, opacity: u8, cel_type: u16) -> Vec<u8> {
+    let mut body = word(layer);
+    body.extend(&x.to_le_bytes());
+    body.extend(&y.to_le_bytes());
+    body.push(opacity);
+    body.extend(word(cel_type));
+    body.extend(&[0; 7]);
+    body
+}
+
+pub fn raw_cel(layer: u16, x: i16, y: i16, width: u16, height: u16, pixels: &[u8]) -> Vec<u8> {
+    let mut body = cel_header(layer, x, y, 255, 0);
+    body.extend(word(width));
+    body.extend(word(height));
+    body.extend_from_slice(pixels);
+    chunk(0x2005, &body)
+}
+
+/// Gives a cel built by one of the functions above the given z-index.
+pub fn z_indexed(mut cel: Vec<u8>, z_index: i16) -> Vec<u8> {
+    cel[15..17].copy_from_slice(&z_index.to_le_bytes());
+    cel
+}
+
+pub fn linked_cel(layer: u16, x: i16, y: i16, frame_position: u16) -> Vec<u8> {
+    let mut body = cel_header(layer, x, y, 255, 1);
+    body.extend(word(frame_position));
+    chunk(0x2005, &body)
+}
+
+pub fn compressed_cel(layer: u16, x: i16, y: i16, width: u16, height: u16, pixels: &[u8]) -> Vec<u8> {
+    let mut body = cel_header(layer, x, y, 255, 2);
+    body.extend(word(width));
+    body.extend(word(height));
+    body.extend(compress(pixels));
+    chunk(0x2005, &body)
+}
+
+pub fn palette(size: u32, first: u32, entries: &[([u8; 4], Option<&str>)]) -> Vec<u8> {
+    let mut body = dword(size);
+    body.extend(dword(first));
+    body.extend(dword(first + entries.len() as u32 - 1));
+    body.extend(&[0; 8]);
+    for (color, name) in entries {
+        body.extend(word(if name.is_some() { 1 } else { 0 }));
+        body.extend(color);
+        if let Some(name) = name {
+            body.extend(string(name));
+        }
+    }
+
+    chunk(0x2019, &body)
+}
+
+pub fn old_palette(chunk_type: u16, packets: &[(u8, Vec<[u8; 3]>)]) -> Vec<u8> {
+    let mut body = word(packets.len() as u16);
+    for (skip, colors) in packets {
+        body.push(*skip);
+        body.push(colors.len() as u8);
+        for color in colors {
+            body.extend(color);
+        }
+    }
+
+    chunk(chunk_type, &body)
+}
+
+/// from frame, to frame, direction, repeat, color, name
+pub type TagSpec<'a> = (u16, u16, u8, u16, [u8; 3], &'a str);
+
+pub fn tags(tags: &[TagSpec]) -> Vec<u8> {
+    let mut body = word(tags.len() as u16);
+    body.extend(&[0; 8]);
+    for (from, to, direction, repeat, color, name) in tags {
+        body.extend(word(*from));
+        body.extend(word(*to));
+        body.push(*direction);
+        body.extend(word(*repeat));
+        body.extend(&[0; 6]);
+        body.extend(color);
+        body.push(0);
+        body.extend(string(name));
+    }
+
+    chunk(0x2018, &body)
+}
+
+/// Each key is a list of LONG/DWORD values following the frame number, laid out the way they
+/// appear in the file.
+pub fn slice(name: &str, flags: u32, keys: &[Vec<i32>]) -> Vec<u8> {
+    let mut body = dword(keys.len() as u32);
+    body.extend(dword(flags));
+    body.extend(dword(0));
+    body.extend(string(name));
+    for key in keys {
+        for value in key {
+            body.extend(&value.to_le_bytes());
+        }
+    }
+
+    chunk(0x2022, &body)
+}
+
+pub fn color_profile(profile_type: u16, flags: u16, gamma: u32, icc: &[u8]) -> Vec<u8> {
+    let mut body = word(profile_type);
+    body.extend(word(flags));
+    body.extend(dword(gamma));
+    body.extend(&[0; 8]);
+    if profile_type == 2 {
+        body.extend(dword(icc.len() as u32));
+        body.extend_from_slice(icc);
+    }
+
+    chunk(0x2007, &body)
+}
+
+/// A minimal matrix/TRC ICC profile describing linear sRGB: the sRGB colorants (adapted to D50)
+/// with identity tone curves.
+pub fn linear_srgb_icc() -> Vec<u8> {
+    fn s15(value: f64) -> [u8; 4] {
+        ((value * 65536.0).round() as i32).to_be_bytes()
+    }
+
+    fn xyz(x: f64, y: f64, z: f64) -> Vec<u8> {
+        let mut tag = b"XYZ \0\0\0\0".to_vec();
+        tag.extend(&s15(x));
+        tag.extend(&s15(y));
+        tag.extend(&s15(z));
+        tag
+    }
+
+    // a curve with a single entry is a plain gamma in u8Fixed8
+    let curve = b"curv\0\0\0\0\0\0\0\x01\x01\0\0\0".to_vec();
+    let tags: Vec<(&[u8; 4], Vec<u8>)> = vec![
+        (b"wtpt", xyz(0.9642, 1.0, 0.8249)),
+        (b"rXYZ", xyz(0.4361, 0.2225, 0.0139)),
+        (b"gXYZ", xyz(0.3851, 0.7169, 0.0971)),
+        (b"bXYZ", xyz(0.1431, 0.0606, 0.7141)),
+        (b"rTRC", curve.clone()),
+        (b"gTRC", curve.clone()),
+        (b"bTRC", curve),
+    ];
+
+    let mut header = vec![0; 128];
+    header[8..12].copy_from_slice(&[2, 0x10, 0, 0]);
+    header[12..16].copy_from_slice(b"mntr");
+    header[16..20].copy_from_slice(b"RGB ");
+    header[20..24].copy_from_slice(b"XYZ ");
+    header[36..40].copy_from_slice(b"acsp");
+    header[68..80].copy_from_slice(&xyz(0.9642, 1.0, 0.8249)[8..]);
+
+    let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+    let mut data: Vec<u8> = Vec::new();
+    let data_start = 128 + 4 + 12 * tags.len();
+    for (signature, tag) in &tags {
+        table.extend(*signature);
+        table.extend(&((data_start + data.len()) as u32).to_be_bytes());
+        table.extend(&(tag.len() as u32).to_be_bytes());
+        data.extend(tag);
+    }
+
+    let mut icc = header;
+    icc.extend(table);
+    icc.extend(data);
+    let size = icc.len() as u32;
+    icc[0..4].copy_from_slice(&size.to_be_bytes());
+    icc
+}
+
+/// Each map is an extension key followed by (name, type, raw value) properties.
+pub type PropertiesSpec<'a> = (u32, Vec<(&'a str, u16, Vec<u8>)>);
+
+pub fn properties(maps: &[PropertiesSpec]) -> Vec<u8> {
+    let mut body = dword(maps.len() as u32);
+    for (key, properties) in maps {
+        body.extend(dword(*key));
+        body.extend(dword(properties.len() as u32));
+        for (name, value_type, value) in properties {
+            body.extend(string(name));
+            body.extend(word(*value_type));
+            body.extend(value);
+        }
+    }
+
+    let mut bytes = dword(4 + body.len() as u32);
+    bytes.extend(body);
+    bytes
+}
+
+pub fn user_data(text: Option<&str>, color: Option<[u8; 4]>, properties: &[u8]) -> Vec<u8> {
+    let mut flags = 0;
+    let mut body = Vec::new();
+    if let Some(text) = text {
+        flags |= 1;
+        body.extend(string(text));
+    }
+
+    if let Some(color) = color {
+        flags |= 2;
+        body.extend(&color);
+    }
+
+    if !properties.is_empty() {
+        flags |= 4;
+        body.extend_from_slice(properties);
+    }
+
+    let mut bytes = dword(flags);
+    bytes.extend(body);
+    chunk(0x2020, &bytes)
+}
+
+pub fn tileset(id: u32, tile_count: u32, tile_width: u16, tile_height: u16, base_index: i16, name: &str, pixels: &[u8]) -> Vec<u8> {
+    let mut body = dword(id);
+    body.extend(dword(2));
+    body.extend(dword(tile_count));
+    body.extend(word(tile_width));
+    body.extend(word(tile_height));
+    body.extend(&base_index.to_le_bytes());
+    body.extend(&[0; 14]);
+    body.extend(string(name));
+    let compressed = compress(pixels);
+    body.extend(dword(compressed.len() as u32));
+    body.extend(compressed);
+    chunk(0x2023, &body)
+}
+
+pub fn tilemap_layer(name: &str, tileset_index: u32) -> Vec<u8> {
+    let mut bytes = layer(name, 1, 2, 0, 0, 255);
+    bytes.extend(dword(tileset_index));
+    let size = bytes.len() as u32;
+    bytes[0..4].copy_from_slice(&dword(size));
+    bytes
+}
+
+/// Tiles are 32-bit with the tile ID in the low 29 bits followed by the x, y and diagonal flips.
+pub fn tilemap_cel(layer: u16, x: i16, y: i16, width: u16, height: u16, tiles: &[u8]) -> Vec<u8> {
+    let mut body = cel_header(layer, x, y, 255, 3);
+    body.extend(word(width));
+    body.extend(word(height));
+    body.extend(word(32));
+    body.extend(dword(0x1fff_ffff));
+    body.extend(dword(0x2000_0000));
+    body.extend(dword(0x4000_0000));
+    body.extend(dword(0x8000_0000));
+    body.extend(&[0; 10]);
+    body.extend(compress(tiles));
+    chunk(0x2005, &body)
+}
+
+pub fn external_files(files: &[(u32, u8, &str)]) -> Vec<u8> {
+    let mut body = dword(files.len() as u32);
+    body.extend(&[0; 8]);
+    for (id, file_type, name) in files {
+        body.extend(dword(*id));
+        body.push(*file_type);
+        body.extend(&[0; 7]);
+        body.extend(string(name));
+    }
+
+    chunk(0x2008, &body)
+}
+
+/// A tileset whose tiles live in the tileset with the given ID in an external file.
+pub fn external_tileset(id: u32, tile_width: u16, tile_height: u16, name: &str, file_id: u32, tileset_id: u32) -> Vec<u8> {
+    let mut body = dword(id);
+    body.extend(dword(1));
+    body.extend(dword(0));
+    body.extend(word(tile_width));
+    body.extend(word(tile_height));
+    body.extend(word(1));
+    body.extend(&[0; 14]);
+    body.extend(string(name));
+    body.extend(dword(file_id));
+    body.extend(dword(tileset_id));
+    chunk(0x2023, &body)
+}
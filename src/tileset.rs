@@ -0,0 +1,195 @@
+use crate::{CelBase, ColorDepth, Pixels, Raw, Result, UserData};
+
+const EXTERNAL_FILE_FLAG: u32 = 1;
+const EMBEDDED_TILES_FLAG: u32 = 2;
+
+/// A set of equally sized tiles that tilemap cels are built out of.
+#[derive(Debug)]
+pub struct Tileset {
+    pub id: u32,
+    pub flags: u32,
+    pub tile_count: u32,
+    pub tile_width: u16,
+    pub tile_height: u16,
+    /// The number the first tile is shown as in the UI. Tile IDs in tilemaps always start at 0.
+    pub base_index: i16,
+    pub name: String,
+    /// Where to find the tiles when they live in another file.
+    pub external: Option<ExternalTileset>,
+    // the tiles in ID order.
+    pub(crate) pixels: Option<Vec<Pixels>>,
+    pub(crate) user_data: Option<UserData>,
+    pub(crate) tile_user_data: Vec<Option<UserData>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExternalTileset {
+    /// The entry ID in the External Files chunk.
+    pub file_id: u32,
+    /// The ID of the tileset within that file.
+    pub tileset_id: u32,
+    // where the reference was read from, for reporting a missing file entry.
+    pub(crate) offset: usize,
+}
+
+impl Tileset {
+    pub(crate) fn new(color_depth: &ColorDepth, raw: Raw) -> Result<Tileset> {
+        let flags = raw.dword(4)?;
+        let tile_count = raw.dword(8)?;
+        let tile_width = raw.word(12)?;
+        let tile_height = raw.word(14)?;
+        // 14 reserved bytes
+        let (name, name_size) = raw.string(32)?;
+        let mut offset = 32 + name_size;
+
+        let external = if flags & EXTERNAL_FILE_FLAG != 0 {
+            let external = ExternalTileset{
+                file_id: raw.dword(offset)?,
+                tileset_id: raw.dword(offset+4)?,
+                offset: raw.offset + offset,
+            };
+            offset += 8;
+            Some(external)
+        } else {
+            None
+        };
+
+        let pixels = if flags & EMBEDDED_TILES_FLAG != 0 {
+            let compressed_size = raw.dword(offset)? as usize;
+            let compressed = raw.slice(offset+4, compressed_size)?;
+            // the file stacks every tile vertically in one image
+            let (width, height) = (tile_width as usize, tile_height as usize);
+            let tile_size = color_depth.data_size(width, height);
+            if tile_size == 0 {
+                return Err(raw.unknown(12, "tile size", 0u32));
+            }
+
+            let size = tile_size.checked_mul(tile_count as usize)
+                .ok_or_else(|| raw.unknown(8, "tile count", tile_count))?;
+            let data = compressed.inflate(0, size)?;
+            let tiles = (0..tile_count as usize)
+                .map(|tile| data[tile * tile_size..(tile + 1) * tile_size].to_vec())
+                .map(|tile| Pixels::new(*color_depth, width, height, tile))
+                .collect();
+            Some(tiles)
+        } else {
+            None
+        };
+
+        Ok(Tileset{
+            id: raw.dword(0)?,
+            flags,
+            tile_count,
+            tile_width,
+            tile_height,
+            base_index: raw.short(16)?,
+            name,
+            external,
+            pixels,
+            user_data: None,
+            tile_user_data: Vec::new(),
+        })
+    }
+
+    /// Whether the tiles' pixels are stored in this file rather than an external one.
+    pub fn has_embedded_tiles(&self) -> bool {
+        self.pixels.is_some()
+    }
+
+    /// Returns the pixels of the tile with the given ID.
+    pub fn tile(&self, tile_id: u32) -> Option<&Pixels> {
+        self.pixels.as_ref()?.get(tile_id as usize)
+    }
+
+    pub fn user_data(&self) -> Option<&UserData> {
+        self.user_data.as_ref()
+    }
+
+    /// The user data attached to the tile with the given ID.
+    pub fn tile_user_data(&self, tile_id: u32) -> Option<&UserData> {
+        self.tile_user_data.get(tile_id as usize).and_then(Option::as_ref)
+    }
+}
+
+/// A cel made out of tiles from the tileset of the layer it's on.
+#[derive(Debug)]
+pub struct TilemapCel {
+    pub(crate) base: CelBase,
+    /// Width in tiles.
+    pub width: u16,
+    /// Height in tiles.
+    pub height: u16,
+    pub bits_per_tile: u16,
+    pub tile_id_mask: u32,
+    pub x_flip_mask: u32,
+    pub y_flip_mask: u32,
+    pub diagonal_flip_mask: u32,
+    tiles: Vec<Tile>,
+}
+
+impl TilemapCel {
+    pub(crate) fn new(raw: Raw) -> Result<TilemapCel> {
+        let offset = CelBase::offset() + 9; // 2 for cel_type, 2 for z_index, 5 reserved
+        let width = raw.word(offset)?;
+        let height = raw.word(offset+2)?;
+        let bits_per_tile = raw.word(offset+4)?;
+        let tile_size = match bits_per_tile {
+            8 | 16 | 32 => bits_per_tile as usize / 8,
+            _ => return Err(raw.unknown(offset+4, "bits per tile", bits_per_tile)),
+        };
+
+        let tile_id_mask = raw.dword(offset+6)?;
+        let x_flip_mask = raw.dword(offset+10)?;
+        let y_flip_mask = raw.dword(offset+14)?;
+        let diagonal_flip_mask = raw.dword(offset+18)?;
+        // 10 reserved bytes
+        let data = raw.inflate(offset+32, width as usize * height as usize * tile_size)?;
+        let tiles = data.chunks(tile_size)
+            .map(|bytes| {
+                let value = bytes.iter().rev().fold(0, |value, byte| value << 8 | *byte as u32);
+                Tile{
+                    id: value & tile_id_mask,
+                    x_flip: value & x_flip_mask != 0,
+                    y_flip: value & y_flip_mask != 0,
+                    diagonal_flip: value & diagonal_flip_mask != 0,
+                }
+            })
+            .collect();
+
+        Ok(TilemapCel{
+            base: CelBase::new(raw)?,
+            width,
+            height,
+            bits_per_tile,
+            tile_id_mask,
+            x_flip_mask,
+            y_flip_mask,
+            diagonal_flip_mask,
+            tiles,
+        })
+    }
+
+    /// Every tile in the map, row by row from top to bottom.
+    pub fn tiles(&self) -> &[Tile] {
+        &self.tiles
+    }
+
+    /// Returns the tile at the given position, measured in tiles.
+    pub fn tile(&self, x: u16, y: u16) -> Option<&Tile> {
+        if x >= self.width {
+            return None;
+        }
+
+        self.tiles.get(y as usize * self.width as usize + x as usize)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    /// Index into the layer's tileset. Tile 0 is usually the empty tile.
+    pub id: u32,
+    pub x_flip: bool,
+    pub y_flip: bool,
+    /// Swaps the x and y axes, which combined with the other flips rotates the tile.
+    pub diagonal_flip: bool,
+}
@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+
+use crate::{Fixed, Raw, Result};
+
+const TEXT_FLAG: u32 = 1;
+const COLOR_FLAG: u32 = 2;
+const PROPERTIES_FLAG: u32 = 4;
+
+/// A named set of properties.
+pub type Properties = BTreeMap<String, Value>;
+
+/// Text, a color and arbitrary properties an artist attached to a layer, cel, tag, slice, etc.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UserData {
+    pub text: Option<String>,
+    pub color: Option<[u8; 4]>,
+    /// Properties maps keyed by the External Files entry ID of the extension that owns them. Key
+    /// 0 holds the properties set by the user.
+    pub properties: BTreeMap<u32, Properties>,
+}
+
+impl UserData {
+    pub(crate) fn new(raw: Raw) -> Result<UserData> {
+        let flags = raw.dword(0)?;
+        let mut user_data = UserData::default();
+        let mut offset = 4;
+        if flags & TEXT_FLAG != 0 {
+            let (text, size) = raw.string(offset)?;
+            user_data.text = Some(text);
+            offset += size;
+        }
+
+        if flags & COLOR_FLAG != 0 {
+            let color = raw.get(offset, 4)?;
+            user_data.color = Some([color[0], color[1], color[2], color[3]]);
+            offset += 4;
+        }
+
+        if flags & PROPERTIES_FLAG != 0 {
+            // the size covers every map, including itself
+            let size = raw.dword(offset)? as usize;
+            let raw = raw.slice(offset, size)?;
+            let count = raw.dword(4)?;
+            let mut offset = 8;
+            for _ in 0..count {
+                let key = raw.dword(offset)?;
+                let (properties, size) = read_properties(raw.rest(offset + 4)?)?;
+                user_data.properties.insert(key, properties);
+                offset += 4 + size;
+            }
+        }
+
+        Ok(user_data)
+    }
+
+    /// The properties set by the user, as opposed to ones owned by an extension.
+    pub fn user_properties(&self) -> Option<&Properties> {
+        self.properties.get(&0)
+    }
+}
+
+/// A single property value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    I8(i8),
+    U8(u8),
+    I16(i16),
+    U16(u16),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    Fixed(Fixed),
+    F32(f32),
+    F64(f64),
+    String(String),
+    Point{
+        x: i32,
+        y: i32,
+    },
+    Size{
+        width: i32,
+        height: i32,
+    },
+    Rect{
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    },
+    Vector(Vec<Value>),
+    Properties(Properties),
+    Uuid([u8; 16]),
+}
+
+impl Value {
+    // returns the value along with the number of bytes it occupied.
+    fn new(value_type: u16, raw: Raw) -> Result<(Value, usize)> {
+        let value = match value_type {
+            0x0001 => (Value::Bool(raw.byte(0)? != 0), 1),
+            0x0002 => (Value::I8(raw.byte(0)? as i8), 1),
+            0x0003 => (Value::U8(raw.byte(0)?), 1),
+            0x0004 => (Value::I16(raw.short(0)?), 2),
+            0x0005 => (Value::U16(raw.word(0)?), 2),
+            0x0006 => (Value::I32(raw.long(0)?), 4),
+            0x0007 => (Value::U32(raw.dword(0)?), 4),
+            0x0008 => (Value::I64(raw.qword(0)? as i64), 8),
+            0x0009 => (Value::U64(raw.qword(0)?), 8),
+            0x000A => (Value::Fixed(raw.fixed(0)?), 4),
+            0x000B => (Value::F32(f32::from_bits(raw.dword(0)?)), 4),
+            0x000C => (Value::F64(f64::from_bits(raw.qword(0)?)), 8),
+            0x000D => {
+                let (value, size) = raw.string(0)?;
+                (Value::String(value), size)
+            },
+            0x000E => (Value::Point{x: raw.long(0)?, y: raw.long(4)?}, 8),
+            0x000F => (Value::Size{width: raw.long(0)?, height: raw.long(4)?}, 8),
+            0x0010 => (Value::Rect{
+                x: raw.long(0)?,
+                y: raw.long(4)?,
+                width: raw.long(8)?,
+                height: raw.long(12)?,
+            }, 16),
+            0x0011 => {
+                let count = raw.dword(0)?;
+                let element_type = raw.word(4)?;
+                let mut elements = Vec::new();
+                let mut offset = 6;
+                for _ in 0..count {
+                    // a zero element type means every element carries its own type
+                    let value_type = if element_type == 0 {
+                        let value_type = raw.word(offset)?;
+                        offset += 2;
+                        value_type
+                    } else {
+                        element_type
+                    };
+
+                    let (element, size) = Value::new(value_type, raw.rest(offset)?)?;
+                    elements.push(element);
+                    offset += size;
+                }
+
+                (Value::Vector(elements), offset)
+            },
+            0x0012 => {
+                let (properties, size) = read_properties(raw)?;
+                (Value::Properties(properties), size)
+            },
+            0x0013 => {
+                let mut uuid = [0; 16];
+                uuid.copy_from_slice(raw.get(0, 16)?);
+                (Value::Uuid(uuid), 16)
+            },
+            _ => return Err(raw.unknown(0, "property type", value_type)),
+        };
+
+        Ok(value)
+    }
+}
+
+// reads a property count followed by that many properties, returning them along with the number
+// of bytes they occupied.
+fn read_properties(raw: Raw) -> Result<(Properties, usize)> {
+    let count = raw.dword(0)?;
+    let mut properties = Properties::new();
+    let mut offset = 4;
+    for _ in 0..count {
+        let (name, name_size) = raw.string(offset)?;
+        offset += name_size;
+        let value_type = raw.word(offset)?;
+        let (value, size) = Value::new(value_type, raw.rest(offset + 2)?)?;
+        offset += 2 + size;
+        properties.insert(name, value);
+    }
+
+    Ok((properties, offset))
+}
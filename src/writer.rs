@@ -0,0 +1,583 @@
+//! Serializes a sprite back into the aseprite format.
+//!
+//! Everything the crate understands is written from the parsed model, so the layout follows
+//! Aseprite's own rather than the original file's. Compressed cels are written back with their
+//! original compressed data, and chunks of unknown types are copied as they were read. Deprecated
+//! chunks (old palettes, masks and paths), cel extras, layer UUIDs and user data that wasn't
+//! attached to anything are dropped. The palette is written once, in the first frame, as it ends
+//! up after every palette chunk in the file has been applied, so palette changes partway through
+//! an animation are lost.
+
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::{
+    Ase, Cel, CelBase, Chunk, ColorDepth, ColorProfile, ExternalFile, ExternalFileType, Layer,
+    LayerType, LoopDirection, Palette, Slice, Tag, TilemapCel, Tileset, UserData, Value,
+    FRAME_MAGIC, HEADER_MAGIC, HEADER_SIZE,
+};
+
+const LAYER_UUIDS_FLAG: u32 = 4;
+
+const EXTERNAL_FILE_FLAG: u32 = 1;
+const EMBEDDED_TILES_FLAG: u32 = 2;
+
+pub(crate) fn write<W: Write>(ase: &Ase, mut writer: W) -> io::Result<()> {
+    let frames = ase.frames.iter().enumerate()
+        .map(|(index, frame)| {
+            let mut chunks = Chunks::default();
+            if index == 0 {
+                write_sprite_chunks(ase, &mut chunks)?;
+            }
+
+            for cel in frame.cels() {
+                chunks.push(0x2005, cel_body(cel)?);
+                chunks.user_data(cel.user_data())?;
+            }
+
+            for chunk in &frame.chunks {
+                if let Chunk::Unknown{chunk_type, data} = chunk {
+                    chunks.push(*chunk_type, data.clone());
+                }
+            }
+
+            chunks.into_frame(frame.frame_duration)
+        })
+        .collect::<io::Result<Vec<Vec<u8>>>>()?;
+
+    let header = &ase.header;
+    let file_size = HEADER_SIZE + frames.iter().map(Vec::len).sum::<usize>();
+    let mut bytes = Vec::with_capacity(HEADER_SIZE);
+    bytes.put_dword(count(file_size, "file size")?);
+    bytes.put_word(HEADER_MAGIC);
+    bytes.put_word(count(frames.len(), "frame count")?);
+    bytes.put_word(header.width);
+    bytes.put_word(header.height);
+    bytes.put_word(match header.color_depth {
+        ColorDepth::RGBA => 32,
+        ColorDepth::GrayScale => 16,
+        ColorDepth::Indexed => 8,
+    });
+    // layer UUIDs aren't kept, so they can't be written either
+    bytes.put_dword(header.flags & !LAYER_UUIDS_FLAG);
+    bytes.put_word(header.speed);
+    bytes.extend_from_slice(&[0; 8]);
+    bytes.push(header.pallette_entry);
+    bytes.extend_from_slice(&[0; 3]);
+    bytes.put_word(header.number_of_colors);
+    bytes.push(header.pixel_width);
+    bytes.push(header.pixel_height);
+    // the grid isn't kept, and the rest is reserved
+    bytes.resize(HEADER_SIZE, 0);
+
+    writer.write_all(&bytes)?;
+    for frame in frames {
+        writer.write_all(&frame)?;
+    }
+
+    writer.flush()
+}
+
+// the chunks describing the whole sprite, which go in the first frame.
+fn write_sprite_chunks(ase: &Ase, chunks: &mut Chunks) -> io::Result<()> {
+    chunks.push(0x2007, color_profile_body(&ase.color_profile)?);
+    if !ase.external_files.is_empty() {
+        chunks.push(0x2008, external_files_body(&ase.external_files)?);
+    }
+
+    // the user data after the first palette belongs to the sprite
+    if !ase.palette.is_empty() {
+        chunks.push(0x2019, palette_body(&ase.palette)?);
+        chunks.user_data(ase.palette.user_data())?;
+    }
+
+    for tileset in &ase.tilesets {
+        chunks.push(0x2023, tileset_body(tileset)?);
+        // the tileset's own user data comes first, then one for each tile
+        if tileset.user_data.is_some() || !tileset.tile_user_data.is_empty() {
+            chunks.push(0x2020, user_data_body(tileset.user_data.as_ref().unwrap_or(&UserData::default()))?);
+            for user_data in &tileset.tile_user_data {
+                chunks.push(0x2020, user_data_body(user_data.as_ref().unwrap_or(&UserData::default()))?);
+            }
+        }
+    }
+
+    for layer in &ase.layers {
+        chunks.push(0x2004, layer_body(layer)?);
+        chunks.user_data(layer.user_data())?;
+    }
+
+    if !ase.tags.is_empty() {
+        chunks.push(0x2018, tags_body(&ase.tags)?);
+        // tags get their user data in order, so every tag up to the last one with some needs one
+        let last = ase.tags.iter().rposition(|tag| tag.user_data.is_some());
+        for tag in ase.tags.iter().take(last.map_or(0, |last| last + 1)) {
+            chunks.push(0x2020, user_data_body(tag.user_data.as_ref().unwrap_or(&UserData::default()))?);
+        }
+    }
+
+    for slice in &ase.slices {
+        chunks.push(0x2022, slice_body(slice)?);
+        chunks.user_data(slice.user_data())?;
+    }
+
+    Ok(())
+}
+
+// the chunks of a frame as they're being built up.
+#[derive(Default)]
+struct Chunks {
+    bytes: Vec<u8>,
+    count: usize,
+}
+
+impl Chunks {
+    fn push(&mut self, chunk_type: u16, body: Vec<u8>) {
+        // a chunk's size includes its own 6 byte header
+        self.bytes.put_dword((body.len() + 6) as u32);
+        self.bytes.put_word(chunk_type);
+        self.bytes.extend(body);
+        self.count += 1;
+    }
+
+    fn user_data(&mut self, user_data: Option<&UserData>) -> io::Result<()> {
+        if let Some(user_data) = user_data {
+            self.push(0x2020, user_data_body(user_data)?);
+        }
+
+        Ok(())
+    }
+
+    fn into_frame(self, duration: u16) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(16 + self.bytes.len());
+        bytes.put_dword(count(16 + self.bytes.len(), "frame size")?);
+        bytes.put_word(FRAME_MAGIC);
+        // the old chunk count saturates, readers use the new one whenever it's set
+        bytes.put_word(u16::try_from(self.count).unwrap_or(u16::MAX));
+        bytes.put_word(duration);
+        bytes.extend_from_slice(&[0; 2]);
+        bytes.put_dword(count(self.count, "chunk count")?);
+        bytes.extend(self.bytes);
+        Ok(bytes)
+    }
+}
+
+fn layer_body(layer: &Layer) -> io::Result<Vec<u8>> {
+    let (default_width, default_height) = layer.default_size();
+    let mut body = Vec::new();
+    body.put_word(layer.flags().bits());
+    body.put_word(match layer.layer_type() {
+        LayerType::Normal => 0,
+        LayerType::Group => 1,
+        LayerType::Tilemap => 2,
+    });
+    body.put_word(layer.child_level());
+    body.put_word(default_width);
+    body.put_word(default_height);
+    body.put_word(layer.blend_mode() as u16);
+    body.push(layer.opacity());
+    body.extend_from_slice(&[0; 3]);
+    body.put_string(layer.name())?;
+    if let Some(tileset_index) = layer.tileset_index() {
+        body.put_dword(tileset_index);
+    }
+
+    Ok(body)
+}
+
+fn cel_body(cel: &Cel) -> io::Result<Vec<u8>> {
+    let (base, cel_type) = match cel {
+        Cel::Raw(c) => (&c.base, 2),
+        Cel::Linked(c) => (&c.base, 1),
+        Cel::Compressed(c) => (&c.base, 2),
+        Cel::Tilemap(c) => (&c.base, 3),
+    };
+
+    let mut body = cel_header(base, cel_type);
+    match cel {
+        // raw cels are written compressed, like Aseprite does
+        Cel::Raw(c) => {
+            body.put_word(c.width);
+            body.put_word(c.height);
+            body.extend(compress(c.pixels.as_bytes())?);
+        },
+        Cel::Linked(c) => body.put_word(c.frame_position),
+        Cel::Compressed(c) => {
+            body.put_word(c.width);
+            body.put_word(c.height);
+            body.extend_from_slice(c.compressed_data());
+        },
+        Cel::Tilemap(c) => tilemap_body(c, &mut body)?,
+    }
+
+    Ok(body)
+}
+
+fn cel_header(base: &CelBase, cel_type: u16) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.put_word(base.layer_index);
+    body.put_short(base.x);
+    body.put_short(base.y);
+    body.push(base.opacity);
+    body.put_word(cel_type);
+    body.put_short(base.z_index);
+    body.extend_from_slice(&[0; 5]);
+    body
+}
+
+fn tilemap_body(cel: &TilemapCel, body: &mut Vec<u8>) -> io::Result<()> {
+    body.put_word(cel.width);
+    body.put_word(cel.height);
+    body.put_word(cel.bits_per_tile);
+    body.put_dword(cel.tile_id_mask);
+    body.put_dword(cel.x_flip_mask);
+    body.put_dword(cel.y_flip_mask);
+    body.put_dword(cel.diagonal_flip_mask);
+    body.extend_from_slice(&[0; 10]);
+
+    let tile_size = cel.bits_per_tile as usize / 8;
+    let mut tiles = Vec::with_capacity(cel.tiles().len() * tile_size);
+    for tile in cel.tiles() {
+        let mut value = tile.id & cel.tile_id_mask;
+        for (flipped, mask) in [
+            (tile.x_flip, cel.x_flip_mask),
+            (tile.y_flip, cel.y_flip_mask),
+            (tile.diagonal_flip, cel.diagonal_flip_mask),
+        ] {
+            if flipped {
+                value |= mask;
+            }
+        }
+
+        tiles.extend_from_slice(&value.to_le_bytes()[..tile_size]);
+    }
+
+    body.extend(compress(&tiles)?);
+    Ok(())
+}
+
+fn color_profile_body(color_profile: &ColorProfile) -> io::Result<Vec<u8>> {
+    let (profile_type, gamma, icc) = match color_profile {
+        ColorProfile::None => (0, None, None),
+        ColorProfile::Srgb{gamma} => (1, *gamma, None),
+        ColorProfile::Icc{data, gamma} => (2, *gamma, Some(data)),
+    };
+
+    let mut body = Vec::new();
+    body.put_word(profile_type);
+    body.put_word(if gamma.is_some() { 1 } else { 0 });
+    body.put_long(gamma.map_or(0, |gamma| (gamma * 65536.0).round() as i32));
+    body.extend_from_slice(&[0; 8]);
+    if let Some(icc) = icc {
+        body.put_dword(count(icc.len(), "ICC profile size")?);
+        body.extend_from_slice(icc);
+    }
+
+    Ok(body)
+}
+
+fn external_files_body(files: &[ExternalFile]) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    body.put_dword(count(files.len(), "external file count")?);
+    body.extend_from_slice(&[0; 8]);
+    for file in files {
+        body.put_dword(file.id);
+        body.push(match file.file_type {
+            ExternalFileType::Palette => 0,
+            ExternalFileType::Tileset => 1,
+            ExternalFileType::PropertiesExtension => 2,
+            ExternalFileType::TileManagementExtension => 3,
+        });
+        body.extend_from_slice(&[0; 7]);
+        body.put_string(&file.name)?;
+    }
+
+    Ok(body)
+}
+
+fn palette_body(palette: &Palette) -> io::Result<Vec<u8>> {
+    let size = count(palette.len(), "palette size")?;
+    let mut body = Vec::new();
+    body.put_dword(size);
+    body.put_dword(0);
+    body.put_dword(size - 1);
+    body.extend_from_slice(&[0; 8]);
+    for entry in palette {
+        body.put_word(entry.flags);
+        body.extend_from_slice(&[entry.red, entry.green, entry.blue, entry.alpha]);
+        if entry.flags & 1 != 0 {
+            body.put_string(&entry.color_name)?;
+        }
+    }
+
+    Ok(body)
+}
+
+fn tileset_body(tileset: &Tileset) -> io::Result<Vec<u8>> {
+    // tiles are embedded whenever their pixels are known, even ones loaded from an external file
+    let mut flags = tileset.flags & !(EXTERNAL_FILE_FLAG | EMBEDDED_TILES_FLAG);
+    if tileset.external.is_some() {
+        flags |= EXTERNAL_FILE_FLAG;
+    }
+
+    if tileset.pixels.is_some() {
+        flags |= EMBEDDED_TILES_FLAG;
+    }
+
+    let mut body = Vec::new();
+    body.put_dword(tileset.id);
+    body.put_dword(flags);
+    body.put_dword(tileset.tile_count);
+    body.put_word(tileset.tile_width);
+    body.put_word(tileset.tile_height);
+    body.put_short(tileset.base_index);
+    body.extend_from_slice(&[0; 14]);
+    body.put_string(&tileset.name)?;
+    if let Some(external) = &tileset.external {
+        body.put_dword(external.file_id);
+        body.put_dword(external.tileset_id);
+    }
+
+    if let Some(tiles) = &tileset.pixels {
+        // the file stacks every tile vertically in one image
+        let data: Vec<u8> = tiles.iter().flat_map(|tile| tile.as_bytes()).copied().collect();
+        let compressed = compress(&data)?;
+        body.put_dword(count(compressed.len(), "tileset data size")?);
+        body.extend(compressed);
+    }
+
+    Ok(body)
+}
+
+fn tags_body(tags: &[Tag]) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    body.put_word(count(tags.len(), "tag count")?);
+    body.extend_from_slice(&[0; 8]);
+    for tag in tags {
+        body.put_word(tag.from_frame);
+        body.put_word(tag.to_frame);
+        body.push(match tag.direction {
+            LoopDirection::Forward => 0,
+            LoopDirection::Reverse => 1,
+            LoopDirection::PingPong => 2,
+            LoopDirection::PingPongReverse => 3,
+        });
+        body.put_word(tag.repeat);
+        body.extend_from_slice(&[0; 6]);
+        body.extend_from_slice(&tag.color);
+        body.push(0);
+        body.put_string(&tag.name)?;
+    }
+
+    Ok(body)
+}
+
+fn slice_body(slice: &Slice) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    body.put_dword(count(slice.keys.len(), "slice key count")?);
+    body.put_dword(slice.flags);
+    body.put_dword(0);
+    body.put_string(&slice.name)?;
+    for key in &slice.keys {
+        body.put_dword(key.frame_number);
+        body.put_long(key.x as i32);
+        body.put_long(key.y as i32);
+        body.put_dword(key.width);
+        body.put_dword(key.height);
+        if slice.has_nine_patch() {
+            body.put_long(key.center_x as i32);
+            body.put_long(key.center_y as i32);
+            body.put_dword(key.center_width);
+            body.put_dword(key.center_height);
+        }
+
+        if slice.has_pivot() {
+            body.put_long(key.pivot_x as i32);
+            body.put_long(key.pivot_y as i32);
+        }
+    }
+
+    Ok(body)
+}
+
+fn user_data_body(user_data: &UserData) -> io::Result<Vec<u8>> {
+    let mut flags = 0;
+    let mut body = Vec::new();
+    if let Some(text) = &user_data.text {
+        flags |= 1;
+        body.put_string(text)?;
+    }
+
+    if let Some(color) = user_data.color {
+        flags |= 2;
+        body.extend_from_slice(&color);
+    }
+
+    if !user_data.properties.is_empty() {
+        flags |= 4;
+        let mut maps = Vec::new();
+        maps.put_dword(count(user_data.properties.len(), "properties map count")?);
+        for (key, properties) in &user_data.properties {
+            maps.put_dword(*key);
+            put_properties(&mut maps, properties)?;
+        }
+
+        // the size covers the size field itself
+        body.put_dword(count(maps.len() + 4, "properties size")?);
+        body.extend(maps);
+    }
+
+    let mut bytes = Vec::with_capacity(4 + body.len());
+    bytes.put_dword(flags);
+    bytes.extend(body);
+    Ok(bytes)
+}
+
+fn put_properties<'a, I>(bytes: &mut Vec<u8>, properties: I) -> io::Result<()>
+where
+    I: IntoIterator<Item = (&'a String, &'a Value)>,
+    I::IntoIter: ExactSizeIterator,
+{
+    let properties = properties.into_iter();
+    bytes.put_dword(count(properties.len(), "property count")?);
+    for (name, value) in properties {
+        bytes.put_string(name)?;
+        bytes.put_word(value_type(value));
+        put_value(bytes, value)?;
+    }
+
+    Ok(())
+}
+
+// the type IDs Value::new reads.
+fn value_type(value: &Value) -> u16 {
+    match value {
+        Value::Bool(_) => 0x0001,
+        Value::I8(_) => 0x0002,
+        Value::U8(_) => 0x0003,
+        Value::I16(_) => 0x0004,
+        Value::U16(_) => 0x0005,
+        Value::I32(_) => 0x0006,
+        Value::U32(_) => 0x0007,
+        Value::I64(_) => 0x0008,
+        Value::U64(_) => 0x0009,
+        Value::Fixed(_) => 0x000A,
+        Value::F32(_) => 0x000B,
+        Value::F64(_) => 0x000C,
+        Value::String(_) => 0x000D,
+        Value::Point{..} => 0x000E,
+        Value::Size{..} => 0x000F,
+        Value::Rect{..} => 0x0010,
+        Value::Vector(_) => 0x0011,
+        Value::Properties(_) => 0x0012,
+        Value::Uuid(_) => 0x0013,
+    }
+}
+
+fn put_value(bytes: &mut Vec<u8>, value: &Value) -> io::Result<()> {
+    match value {
+        Value::Bool(value) => bytes.push(*value as u8),
+        Value::I8(value) => bytes.push(*value as u8),
+        Value::U8(value) => bytes.push(*value),
+        Value::I16(value) => bytes.put_short(*value),
+        Value::U16(value) => bytes.put_word(*value),
+        Value::I32(value) => bytes.put_long(*value),
+        Value::U32(value) => bytes.put_dword(*value),
+        Value::I64(value) => bytes.put_qword(*value as u64),
+        Value::U64(value) => bytes.put_qword(*value),
+        Value::Fixed(value) => bytes.put_long(value.to_bits()),
+        Value::F32(value) => bytes.put_dword(value.to_bits()),
+        Value::F64(value) => bytes.put_qword(value.to_bits()),
+        Value::String(value) => bytes.put_string(value)?,
+        Value::Point{x, y} => {
+            bytes.put_long(*x);
+            bytes.put_long(*y);
+        },
+        Value::Size{width, height} => {
+            bytes.put_long(*width);
+            bytes.put_long(*height);
+        },
+        Value::Rect{x, y, width, height} => {
+            bytes.put_long(*x);
+            bytes.put_long(*y);
+            bytes.put_long(*width);
+            bytes.put_long(*height);
+        },
+        Value::Vector(elements) => {
+            bytes.put_dword(count(elements.len(), "vector length")?);
+            // a shared element type is written once, mixed vectors give each element its own
+            let element_type = elements.first().map_or(0, value_type);
+            if elements.iter().all(|element| value_type(element) == element_type) {
+                bytes.put_word(element_type);
+                for element in elements {
+                    put_value(bytes, element)?;
+                }
+            } else {
+                bytes.put_word(0);
+                for element in elements {
+                    bytes.put_word(value_type(element));
+                    put_value(bytes, element)?;
+                }
+            }
+        },
+        Value::Properties(properties) => put_properties(bytes, properties)?,
+        Value::Uuid(uuid) => bytes.extend_from_slice(uuid),
+    }
+
+    Ok(())
+}
+
+fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+// converts a length into the integer type the file stores it as, failing for sprites too big to
+// be written.
+fn count<T: TryFrom<usize>>(value: usize, what: &str) -> io::Result<T> {
+    T::try_from(value).map_err(|_| io::Error::new(
+        io::ErrorKind::InvalidInput, format!("{} of {} is too large to be written", what, value)
+    ))
+}
+
+// little-endian writers, the counterparts of Raw's readers.
+trait Put {
+    fn put_word(&mut self, value: u16);
+    fn put_short(&mut self, value: i16);
+    fn put_dword(&mut self, value: u32);
+    fn put_long(&mut self, value: i32);
+    fn put_qword(&mut self, value: u64);
+    fn put_string(&mut self, value: &str) -> io::Result<()>;
+}
+
+impl Put for Vec<u8> {
+    fn put_word(&mut self, value: u16) {
+        self.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_short(&mut self, value: i16) {
+        self.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_dword(&mut self, value: u32) {
+        self.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_long(&mut self, value: i32) {
+        self.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_qword(&mut self, value: u64) {
+        self.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_string(&mut self, value: &str) -> io::Result<()> {
+        self.put_word(count(value.len(), "string length")?);
+        self.extend_from_slice(value.as_bytes());
+        Ok(())
+    }
+}